@@ -1,10 +1,10 @@
 use mania_converter::malody_func::process_whole_dir_mcz;
-use mania_converter::osu_func::parse_whole_dir_osz;
+use mania_converter::osu_func::parse_whole_dir_archives;
 use std::io::{self, Write};
 
 fn main() -> io::Result<()> {
     let mode = read_bool_input(
-        "Please choose mode (y for mcz converter, n for osz info card): ",
+        "Please choose mode (y for mcz converter, n for osz/mcz info card): ",
         true, // 默认值
     )?;
 
@@ -30,7 +30,7 @@ fn main() -> io::Result<()> {
         println!("\nPress Enter to exit...");
         io::stdin().read_line(&mut String::new())?;
     } else {
-        let locations = parse_whole_dir_osz("")?;
+        let locations = parse_whole_dir_archives("")?;
         println!("\nInfo cards generated successfully! Locations:");
         for p in locations {
             println!("{p}")
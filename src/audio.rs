@@ -0,0 +1,263 @@
+use std::fs::File;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+/// 交错排列的 PCM 音频数据，采样值范围 [-1, 1]
+#[derive(Debug, Clone)]
+pub struct PcmAudio {
+    pub sample_rate: u32,
+    pub channels: u16,
+    pub samples: Vec<f32>,
+}
+
+impl PcmAudio {
+    pub fn frame_count(&self) -> usize {
+        self.samples.len() / self.channels.max(1) as usize
+    }
+
+    pub fn duration_ms(&self) -> f64 {
+        self.frame_count() as f64 * 1000.0 / self.sample_rate as f64
+    }
+}
+
+/// 变速时的音频处理方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StretchMode {
+    /// 直接重采样，音高随速度变化（类似 NC/DC）
+    Resample,
+    /// 保持音高的时间拉伸（类似 DT/HT）
+    TimeStretch,
+}
+
+/// 可替换的音频变速后端
+pub trait AudioBackend: Sync {
+    /// 变速后音频文件的扩展名（不含点）
+    fn output_extension(&self, input: &Path) -> String;
+
+    /// 将 `input` 以 `rate` 倍速处理后写入 `output`
+    fn change_rate(&self, input: &Path, output: &Path, rate: f64) -> io::Result<()>;
+}
+
+/// 内置的纯 Rust 实现：读取 WAV/OGG，输出 16 bit WAV
+#[derive(Debug, Clone, Copy)]
+pub struct BuiltinAudioBackend {
+    pub mode: StretchMode,
+}
+
+impl Default for BuiltinAudioBackend {
+    fn default() -> Self {
+        Self {
+            mode: StretchMode::TimeStretch,
+        }
+    }
+}
+
+impl AudioBackend for BuiltinAudioBackend {
+    fn output_extension(&self, _input: &Path) -> String {
+        "wav".into()
+    }
+
+    fn change_rate(&self, input: &Path, output: &Path, rate: f64) -> io::Result<()> {
+        let audio = decode_file(input)?;
+        let processed = match self.mode {
+            StretchMode::Resample => resample(&audio, rate),
+            StretchMode::TimeStretch => time_stretch(&audio, rate),
+        };
+        write_wav(output, &processed)
+    }
+}
+
+/// 根据扩展名解码 WAV 或 OGG 文件
+pub fn decode_file(path: &Path) -> io::Result<PcmAudio> {
+    let extension = path
+        .extension()
+        .and_then(|e| e.to_str())
+        .map(|e| e.to_ascii_lowercase())
+        .unwrap_or_default();
+    match extension.as_str() {
+        "wav" => decode_wav(path),
+        "ogg" => decode_ogg(path),
+        _ => Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("Unsupported audio format: {}", path.display()),
+        )),
+    }
+}
+
+/// 音频时长（毫秒），仅支持内置解码器能处理的格式
+pub fn duration_ms(path: &Path) -> io::Result<f64> {
+    decode_file(path).map(|a| a.duration_ms())
+}
+
+fn decode_wav(path: &Path) -> io::Result<PcmAudio> {
+    let reader = hound::WavReader::open(path).map_err(to_io_error)?;
+    let spec = reader.spec();
+    let samples: Vec<f32> = match spec.sample_format {
+        hound::SampleFormat::Float => reader
+            .into_samples::<f32>()
+            .collect::<Result<_, _>>()
+            .map_err(to_io_error)?,
+        hound::SampleFormat::Int => {
+            let scale = (1i64 << (spec.bits_per_sample - 1)) as f32;
+            reader
+                .into_samples::<i32>()
+                .map(|s| s.map(|v| v as f32 / scale))
+                .collect::<Result<_, _>>()
+                .map_err(to_io_error)?
+        }
+    };
+    Ok(PcmAudio {
+        sample_rate: spec.sample_rate,
+        channels: spec.channels,
+        samples,
+    })
+}
+
+fn decode_ogg(path: &Path) -> io::Result<PcmAudio> {
+    let file = File::open(path)?;
+    let mut reader =
+        lewton::inside_ogg::OggStreamReader::new(BufReader::new(file)).map_err(to_io_error)?;
+    let sample_rate = reader.ident_hdr.audio_sample_rate;
+    let channels = reader.ident_hdr.audio_channels as u16;
+
+    let mut samples = Vec::new();
+    while let Some(packet) = reader.read_dec_packet_itl().map_err(to_io_error)? {
+        samples.extend(packet.iter().map(|&s| s as f32 / 32768.0));
+    }
+    Ok(PcmAudio {
+        sample_rate,
+        channels,
+        samples,
+    })
+}
+
+pub fn write_wav(path: &Path, audio: &PcmAudio) -> io::Result<()> {
+    let spec = hound::WavSpec {
+        channels: audio.channels,
+        sample_rate: audio.sample_rate,
+        bits_per_sample: 16,
+        sample_format: hound::SampleFormat::Int,
+    };
+    let mut writer = hound::WavWriter::create(path, spec).map_err(to_io_error)?;
+    for &s in &audio.samples {
+        let v = (s.clamp(-1.0, 1.0) * 32767.0).round() as i16;
+        writer.write_sample(v).map_err(to_io_error)?;
+    }
+    writer.finalize().map_err(to_io_error)
+}
+
+/// 线性插值重采样，播放速度与音高同时变为 `rate` 倍
+pub fn resample(audio: &PcmAudio, rate: f64) -> PcmAudio {
+    let channels = audio.channels.max(1) as usize;
+    let in_frames = audio.frame_count();
+    let out_frames = (in_frames as f64 / rate).floor() as usize;
+
+    let mut samples = Vec::with_capacity(out_frames * channels);
+    for i in 0..out_frames {
+        let pos = i as f64 * rate;
+        let idx = pos.floor() as usize;
+        let frac = (pos - idx as f64) as f32;
+        let next = (idx + 1).min(in_frames.saturating_sub(1));
+        for c in 0..channels {
+            let a = audio.samples[idx * channels + c];
+            let b = audio.samples[next * channels + c];
+            samples.push(a + (b - a) * frac);
+        }
+    }
+
+    PcmAudio {
+        sample_rate: audio.sample_rate,
+        channels: audio.channels,
+        samples,
+    }
+}
+
+/// WSOLA 时间拉伸：长度变为 1/`rate`，音高不变
+pub fn time_stretch(audio: &PcmAudio, rate: f64) -> PcmAudio {
+    const FRAME: usize = 2048;
+    const SYNTH_HOP: usize = FRAME / 2;
+    const SEEK: usize = 256;
+
+    let channels = audio.channels.max(1) as usize;
+    let in_frames = audio.frame_count();
+    let out_frames = (in_frames as f64 / rate).floor() as usize;
+    if in_frames < FRAME + SEEK {
+        return resample(audio, rate);
+    }
+
+    let window: Vec<f32> = (0..FRAME)
+        .map(|i| {
+            let x = std::f32::consts::PI * 2.0 * i as f32 / FRAME as f32;
+            0.5 - 0.5 * x.cos()
+        })
+        .collect();
+    let mono: Vec<f32> = audio
+        .samples
+        .chunks(channels)
+        .map(|f| f.iter().sum::<f32>() / channels as f32)
+        .collect();
+
+    let mut output = vec![0.0f32; (out_frames + FRAME) * channels];
+    let mut norm = vec![0.0f32; out_frames + FRAME];
+    // 上一帧在输入中的位置，用于寻找最相似的衔接点
+    let mut prev_input = 0usize;
+    let mut out_pos = 0usize;
+
+    while out_pos < out_frames {
+        let nominal = (out_pos as f64 * rate) as usize;
+        let natural = prev_input + SYNTH_HOP;
+        let input_pos = if out_pos == 0 {
+            0
+        } else {
+            let lo = nominal.saturating_sub(SEEK);
+            let hi = (nominal + SEEK).min(in_frames.saturating_sub(FRAME));
+            let similarity = |p: usize| -> f32 {
+                (0..SYNTH_HOP)
+                    .step_by(8)
+                    .filter(|&i| p + i < in_frames && natural + i < in_frames)
+                    .map(|i| mono[p + i] * mono[natural + i])
+                    .sum()
+            };
+            (lo..=hi.max(lo))
+                .step_by(4)
+                .map(|p| (p, similarity(p)))
+                .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(std::cmp::Ordering::Equal))
+                .map(|(p, _)| p)
+                .unwrap_or(nominal)
+        };
+
+        for (i, &w) in window.iter().enumerate() {
+            let src = input_pos + i;
+            if src >= in_frames {
+                break;
+            }
+            for c in 0..channels {
+                output[(out_pos + i) * channels + c] += audio.samples[src * channels + c] * w;
+            }
+            norm[out_pos + i] += w;
+        }
+
+        prev_input = input_pos;
+        out_pos += SYNTH_HOP;
+    }
+
+    output.truncate(out_frames * channels);
+    for (i, frame) in output.chunks_mut(channels).enumerate() {
+        if norm[i] > 1e-3 {
+            frame.iter_mut().for_each(|s| *s /= norm[i]);
+        }
+    }
+
+    PcmAudio {
+        sample_rate: audio.sample_rate,
+        channels: audio.channels,
+        samples: output,
+    }
+}
+
+fn to_io_error<E>(e: E) -> io::Error
+where
+    E: std::error::Error + Send + Sync + 'static,
+{
+    io::Error::new(io::ErrorKind::InvalidData, e)
+}
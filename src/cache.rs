@@ -0,0 +1,178 @@
+// 谱面信息缓存：以谱面内容的 MD5 与算法版本为键，避免批量处理时重复计算星级
+
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use crate::osu_func::SrReport;
+use crate::BeatMapInfo;
+
+/// 星级等算法的版本号。
+/// 修改任何会影响 `BeatMapInfo` 或 `SrReport` 结果的算法时需要递增，旧缓存会整体失效
+pub const ALGORITHM_VERSION: u32 = 4;
+
+/// 批量处理时在目标目录下使用的缓存文件名
+pub const CACHE_FILE_NAME: &str = ".mania_converter_cache.json";
+
+/// 单个谱面的缓存内容
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct CacheEntry {
+    pub info: Option<BeatMapInfo>,
+    /// 星级计算的详细结果
+    pub report: Option<SrReport>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct CacheFile {
+    version: u32,
+    entries: HashMap<String, CacheEntry>,
+}
+
+/// 保存在磁盘上的 JSON 缓存，可在多个线程中共享
+pub struct InfoCache {
+    path: PathBuf,
+    entries: Mutex<HashMap<String, CacheEntry>>,
+    dirty: Mutex<bool>,
+}
+
+impl InfoCache {
+    /// 打开缓存文件。
+    /// 文件不存在、无法解析或版本号与 `ALGORITHM_VERSION` 不一致时得到空缓存
+    pub fn open<P: AsRef<Path>>(path: P) -> Self {
+        let path = path.as_ref().to_path_buf();
+        let entries = File::open(&path)
+            .ok()
+            .and_then(|file| serde_json::from_reader::<_, CacheFile>(BufReader::new(file)).ok())
+            .filter(|cache| cache.version == ALGORITHM_VERSION)
+            .map(|cache| cache.entries)
+            .unwrap_or_default();
+        Self {
+            path,
+            entries: Mutex::new(entries),
+            dirty: Mutex::new(false),
+        }
+    }
+
+    /// 打开目录下的默认缓存文件
+    pub fn open_in_dir<P: AsRef<Path>>(dir: P) -> Self {
+        Self::open(dir.as_ref().join(CACHE_FILE_NAME))
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// 由谱面文件内容与影响结果的选项生成缓存键。
+    /// 选项不同的结果互不覆盖，例如 `"sr"` 与 `"nosr"`
+    pub fn key(content: &[u8], options: &str) -> String {
+        format!("{:x}:{}", md5::compute(content), options)
+    }
+
+    /// 读取文件并生成缓存键
+    pub fn key_for_file<P: AsRef<Path>>(path: P, options: &str) -> io::Result<String> {
+        Ok(Self::key(&fs::read(path)?, options))
+    }
+
+    pub fn get(&self, key: &str) -> Option<CacheEntry> {
+        self.entries.lock().unwrap().get(key).cloned()
+    }
+
+    pub fn get_info(&self, key: &str) -> Option<BeatMapInfo> {
+        self.get(key).and_then(|entry| entry.info)
+    }
+
+    pub fn get_report(&self, key: &str) -> Option<SrReport> {
+        self.get(key).and_then(|entry| entry.report)
+    }
+
+    pub fn insert_info(&self, key: &str, info: BeatMapInfo) {
+        self.entries
+            .lock()
+            .unwrap()
+            .entry(key.to_string())
+            .or_default()
+            .info = Some(info);
+        *self.dirty.lock().unwrap() = true;
+    }
+
+    pub fn insert_report(&self, key: &str, report: SrReport) {
+        self.entries
+            .lock()
+            .unwrap()
+            .entry(key.to_string())
+            .or_default()
+            .report = Some(report);
+        *self.dirty.lock().unwrap() = true;
+    }
+
+    /// 命中缓存时直接返回谱面信息与星级的详细结果，否则调用 `f` 计算并写入缓存。
+    /// 计算过程不持有锁，多个线程可同时计算不同的谱面；计算失败时不写入缓存
+    pub fn info_with_report_or_insert_with<F>(
+        &self,
+        key: &str,
+        f: F,
+    ) -> io::Result<(BeatMapInfo, Option<SrReport>)>
+    where
+        F: FnOnce() -> io::Result<(BeatMapInfo, Option<SrReport>)>,
+    {
+        if let Some(CacheEntry {
+            info: Some(info),
+            report,
+        }) = self.get(key)
+        {
+            return Ok((info, report));
+        }
+        let (info, report) = f()?;
+        let entry = CacheEntry {
+            info: Some(info.clone()),
+            report,
+        };
+        self.entries.lock().unwrap().insert(key.to_string(), entry);
+        *self.dirty.lock().unwrap() = true;
+        Ok((info, report))
+    }
+
+    pub fn remove(&self, key: &str) -> Option<CacheEntry> {
+        let removed = self.entries.lock().unwrap().remove(key);
+        if removed.is_some() {
+            *self.dirty.lock().unwrap() = true;
+        }
+        removed
+    }
+
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+        *self.dirty.lock().unwrap() = true;
+    }
+
+    /// 有改动时写回磁盘。先写入临时文件再重命名，避免中断时留下损坏的缓存
+    pub fn save(&self) -> io::Result<()> {
+        let mut dirty = self.dirty.lock().unwrap();
+        if !*dirty {
+            return Ok(());
+        }
+        let cache = CacheFile {
+            version: ALGORITHM_VERSION,
+            entries: self.entries.lock().unwrap().clone(),
+        };
+        let temp_path = self.path.with_extension("json.tmp");
+        {
+            let writer = BufWriter::new(File::create(&temp_path)?);
+            serde_json::to_writer(writer, &cache)?;
+        }
+        fs::rename(&temp_path, &self.path)?;
+        *dirty = false;
+        Ok(())
+    }
+}
@@ -1,16 +1,128 @@
+mod assets;
+mod card_template;
+mod chart_preview;
+mod density_graph;
 mod info_generation;
+mod output;
+mod scroll_preview;
+mod strain_graph;
+mod title_image;
 
+use std::borrow::Cow;
+use std::fs;
 use std::io;
 use std::path::{Path, PathBuf};
 
-pub use self::info_generation::generate_info_abstract;
-use crate::osu_func::parse_osz_postprocess;
+pub use self::assets::{
+    asset_dir, set_asset_dir, set_font_dirs, set_system_fonts, template_source,
+};
+pub use self::card_template::{CardLayout, CardOptions, CardTemplates, CardTheme};
+pub use self::chart_preview::{generate_chart_preview, render_chart_preview_svg, save_chart_preview};
+pub use self::density_graph::{
+    density_curve, generate_density_graph, render_density_graph_svg, save_density_graph,
+};
+pub use self::info_generation::{
+    card_file_name, generate_info_abstract, generate_info_cards, render_info_cards,
+    render_info_cards_svg,
+};
+pub use self::output::OutputFormat;
+pub use self::scroll_preview::{
+    generate_scroll_graph, render_scroll_frames_svg, render_scroll_graph_svg, save_scroll_frames,
+    save_scroll_graph, ScrollChart, ScrollCurve,
+};
+pub use self::strain_graph::{generate_strain_graph, render_strain_graph_svg, save_strain_graph};
+pub use self::title_image::render_title_image;
+use crate::cache::InfoCache;
+use self::info_generation::render_svg_to_png;
+use crate::malody_func::McData;
+use crate::misc::sanitize_filename;
+use crate::osu_func::{parse_chart_postprocess_with_cache, OsuDataLegacy, OsuDataV128, OsuMisc};
+
+/// 可以用于绘制 NPS 曲线、谱面预览与滚动模拟的谱面
+pub trait ChartSource {
+    fn chart_data(&self) -> io::Result<Cow<'_, OsuDataLegacy>>;
+
+    /// 模拟滚动所需的信息，默认由 osu! 的时间点计算
+    fn scroll_chart(&self) -> io::Result<ScrollChart> {
+        Ok(ScrollChart::from_osu(&*self.chart_data()?))
+    }
+}
+
+impl ChartSource for OsuDataLegacy {
+    fn chart_data(&self) -> io::Result<Cow<'_, OsuDataLegacy>> {
+        Ok(Cow::Borrowed(self))
+    }
+}
+
+impl ChartSource for OsuDataV128 {
+    fn chart_data(&self) -> io::Result<Cow<'_, OsuDataLegacy>> {
+        Ok(Cow::Owned(self.clone().to_legacy()))
+    }
+}
+
+impl ChartSource for McData {
+    fn chart_data(&self) -> io::Result<Cow<'_, OsuDataLegacy>> {
+        self.to_osu_data().map(Cow::Owned)
+    }
+
+    /// 直接使用 Malody 的变速效果，以便与转换后的 osu! 谱面对比
+    fn scroll_chart(&self) -> io::Result<ScrollChart> {
+        Ok(ScrollChart::from_mc(self))
+    }
+}
+
+/// 保存渲染好的 SVG：扩展名为 .svg 时直接写入，否则按扩展名编码为位图（默认 PNG），
+/// 图片引用的资源在 `pic_path` 所在目录下查找
+fn save_svg(svg_content: &str, width: u32, height: u32, pic_path: &Path) -> io::Result<()> {
+    if let Some(parent) = pic_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    if pic_path.extension() == Some(std::ffi::OsStr::new("svg")) {
+        fs::write(pic_path, svg_content)
+    } else {
+        let resources_dir = pic_path.parent().unwrap_or(Path::new("."));
+        render_svg_to_png(svg_content, resources_dir, width, height, pic_path)
+    }
+}
+
+/// 图片标题 "艺术家 - 标题 [难度名]"
+fn chart_title(misc: &OsuMisc) -> String {
+    format!("{} - {} [{}]", misc.artist, misc.title, misc.version)
+}
+
+/// `save_pic_path` 下的图片路径，文件名为 "标题 [难度名]{suffix}.png"
+fn chart_pic_path(misc: &OsuMisc, save_pic_path: &Path, suffix: &str) -> PathBuf {
+    let name = sanitize_filename(&format!("{} [{}]", misc.title, misc.version));
+    save_pic_path.join(format!("{}{}.png", name, suffix))
+}
 
 pub fn generate_osz_info(osz_path: &Path) -> io::Result<PathBuf> {
-    let save_pic_path = osz_path.parent().unwrap();
+    generate_osz_info_with_cache(osz_path, None)
+}
+
+/// 同 [`generate_osz_info`]，命中缓存的谱面不再重新计算星级
+pub fn generate_osz_info_with_cache(
+    osz_path: &Path,
+    cache: Option<&InfoCache>,
+) -> io::Result<PathBuf> {
+    generate_chart_info_with_cache(osz_path, cache)
+}
+
+/// 为 .osz/.mcz 压缩包、单个 .osu/.mc 谱面或解压后的歌曲目录生成信息卡，
+/// 图片保存在输入路径所在的目录下
+pub fn generate_chart_info(path: &Path) -> io::Result<PathBuf> {
+    generate_chart_info_with_cache(path, None)
+}
+
+/// 同 [`generate_chart_info`]，命中缓存的谱面不再重新计算星级
+pub fn generate_chart_info_with_cache(
+    path: &Path,
+    cache: Option<&InfoCache>,
+) -> io::Result<PathBuf> {
+    let save_pic_path = path.parent().unwrap_or(Path::new("."));
     let mut pic_path = PathBuf::new();
-    parse_osz_postprocess(osz_path, |info_vec, temp_dir_path| {
-        pic_path = generate_info_abstract(info_vec, temp_dir_path, save_pic_path)?;
+    parse_chart_postprocess_with_cache(path, cache, |info_vec, resources_dir| {
+        pic_path = generate_info_abstract(info_vec, resources_dir, save_pic_path)?;
         Ok(())
     })?;
     Ok(pic_path)
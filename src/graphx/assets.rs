@@ -0,0 +1,225 @@
+use base64::Engine;
+use handlebars::Handlebars;
+use lazy_static::lazy_static;
+use resvg::usvg::fontdb;
+use serde::Serialize;
+use std::{
+    borrow::Cow,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+
+/// 编译进库中的默认模板，名称与 svg 目录下的文件名（不含扩展名）一致
+const EMBEDDED_TEMPLATES: [(&str, &str); 10] = [
+    ("info_card", include_str!("../../svg/info_card.svg")),
+    ("info_grid", include_str!("../../svg/info_grid.svg")),
+    ("info_compact", include_str!("../../svg/info_compact.svg")),
+    ("strain_graph", include_str!("../../svg/strain_graph.svg")),
+    ("density_graph", include_str!("../../svg/density_graph.svg")),
+    ("chart_preview", include_str!("../../svg/chart_preview.svg")),
+    ("scroll_graph", include_str!("../../svg/scroll_graph.svg")),
+    ("scroll_frames", include_str!("../../svg/scroll_frames.svg")),
+    ("info_single", include_str!("../../svg/info_single.svg")),
+    ("title_image", include_str!("../../svg/title_image.svg")),
+];
+/// 没有背景图时使用的占位图
+const NO_IMAGE: &[u8] = include_bytes!("../../svg/no_image.jpg");
+const NO_IMAGE_NAME: &str = "no_image.jpg";
+/// 未设置字体目录时，若当前目录下存在该目录则从中加载字体
+const DEFAULT_FONT_DIR: &str = "./font";
+/// 模板中使用的字体，找不到时回退到系统字体
+const PREFERRED_FAMILIES: [&str; 2] = ["Source Han Sans SC", "Source Han Sans"];
+
+struct AssetState {
+    font_dirs: Option<Vec<PathBuf>>,
+    asset_dir: Option<PathBuf>,
+    system_fonts: bool,
+    /// 按需加载，配置变化时清空
+    fonts: Option<Arc<fontdb::Database>>,
+    registry: Handlebars<'static>,
+}
+
+lazy_static! {
+    static ref ASSETS: RwLock<AssetState> = RwLock::new(AssetState {
+        font_dirs: None,
+        asset_dir: None,
+        system_fonts: true,
+        fonts: None,
+        registry: Handlebars::new(),
+    });
+    static ref NO_IMAGE_URI: String = format!(
+        "data:image/jpeg;base64,{}",
+        base64::engine::general_purpose::STANDARD.encode(NO_IMAGE)
+    );
+}
+
+fn lock_error() -> io::Error {
+    io::Error::other("Asset state is poisoned")
+}
+
+fn check_dir(dir: &Path) -> io::Result<()> {
+    if dir.is_dir() {
+        Ok(())
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("Directory not found: {}", dir.display()),
+        ))
+    }
+}
+
+/// 设置加载字体的目录，替换默认的 "./font"。传入空列表则只使用系统字体
+pub fn set_font_dirs<P: AsRef<Path>>(dirs: &[P]) -> io::Result<()> {
+    let dirs: Vec<PathBuf> = dirs.iter().map(|d| d.as_ref().to_path_buf()).collect();
+    for dir in &dirs {
+        check_dir(dir)?;
+    }
+    let mut state = ASSETS.write().map_err(|_| lock_error())?;
+    state.font_dirs = Some(dirs);
+    state.fonts = None;
+    Ok(())
+}
+
+/// 是否加载系统字体，默认加载。关闭后若字体目录中没有字体，文字将无法显示
+pub fn set_system_fonts(enabled: bool) -> io::Result<()> {
+    let mut state = ASSETS.write().map_err(|_| lock_error())?;
+    state.system_fonts = enabled;
+    state.fonts = None;
+    Ok(())
+}
+
+/// 设置资源目录。目录中与内置模板同名的 .svg 文件（如 "info_card.svg"）
+/// 以及 "no_image.jpg" 会替代编译进库中的默认资源；传入 None 则恢复默认
+pub fn set_asset_dir(dir: Option<&Path>) -> io::Result<()> {
+    if let Some(dir) = dir {
+        check_dir(dir)?;
+    }
+    let mut state = ASSETS.write().map_err(|_| lock_error())?;
+    state.asset_dir = dir.map(Path::to_path_buf);
+    state.registry.clear_templates();
+    Ok(())
+}
+
+/// 当前使用的资源目录
+pub fn asset_dir() -> Option<PathBuf> {
+    ASSETS.read().ok()?.asset_dir.clone()
+}
+
+/// 名为 `name` 的模板内容，资源目录中的文件优先于内置模板
+pub fn template_source(name: &str) -> io::Result<Cow<'static, str>> {
+    if let Some(path) = asset_dir().map(|d| d.join(format!("{}.svg", name))) {
+        if path.is_file() {
+            return fs::read_to_string(path).map(Cow::Owned);
+        }
+    }
+    EMBEDDED_TEMPLATES
+        .iter()
+        .find(|(n, _)| *n == name)
+        .map(|(_, content)| Cow::Borrowed(*content))
+        .ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Template not found: {}", name),
+            )
+        })
+}
+
+/// 使用内置（或资源目录中的）模板渲染 SVG，模板在首次使用时注册
+pub(crate) fn render_template<T: Serialize>(name: &str, context: &T) -> io::Result<String> {
+    {
+        let state = ASSETS.read().map_err(|_| lock_error())?;
+        if state.registry.has_template(name) {
+            return state
+                .registry
+                .render(name, context)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e));
+        }
+    }
+    let source = template_source(name)?;
+    let mut state = ASSETS.write().map_err(|_| lock_error())?;
+    state
+        .registry
+        .register_template_string(name, source.as_ref())
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    state
+        .registry
+        .render(name, context)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+}
+
+/// 占位图的链接：资源目录中的文件路径，或内置图片的 data URI
+pub(crate) fn no_image_href() -> String {
+    match asset_dir().map(|d| d.join(NO_IMAGE_NAME)) {
+        Some(path) if path.is_file() => path.to_string_lossy().into_owned(),
+        _ => NO_IMAGE_URI.clone(),
+    }
+}
+
+/// 将图片文件编码为 data URI，用于输出不依赖外部文件的 SVG
+pub(crate) fn image_data_uri(path: &Path) -> io::Result<String> {
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_ascii_lowercase());
+    let mime = match extension.as_deref() {
+        Some("png") => "image/png",
+        Some("webp") => "image/webp",
+        Some("gif") => "image/gif",
+        Some("bmp") => "image/bmp",
+        _ => "image/jpeg",
+    };
+    Ok(format!(
+        "data:{};base64,{}",
+        mime,
+        base64::engine::general_purpose::STANDARD.encode(fs::read(path)?)
+    ))
+}
+
+/// 渲染用的字体数据库，首次使用时按当前配置加载
+pub(crate) fn fonts() -> io::Result<Arc<fontdb::Database>> {
+    if let Some(fonts) = ASSETS.read().map_err(|_| lock_error())?.fonts.clone() {
+        return Ok(fonts);
+    }
+    let mut state = ASSETS.write().map_err(|_| lock_error())?;
+    // 等待写锁期间可能已由其他线程加载
+    if let Some(fonts) = state.fonts.clone() {
+        return Ok(fonts);
+    }
+    let mut db = fontdb::Database::new();
+    match &state.font_dirs {
+        Some(dirs) => dirs.iter().for_each(|dir| db.load_fonts_dir(dir)),
+        None => {
+            if Path::new(DEFAULT_FONT_DIR).is_dir() {
+                db.load_fonts_dir(DEFAULT_FONT_DIR);
+            }
+        }
+    }
+    if state.system_fonts {
+        db.load_system_fonts();
+    }
+    set_fallback_families(&mut db);
+    let fonts = Arc::new(db);
+    state.fonts = Some(fonts.clone());
+    Ok(fonts)
+}
+
+/// 模板中的字体不存在时，usvg 会回退到 serif 字体族，
+/// 这里将其指向首选字体或数据库中任意一个可用的字体
+fn set_fallback_families(db: &mut fontdb::Database) {
+    let has_family = |db: &fontdb::Database, family: &str| {
+        db.faces()
+            .any(|face| face.families.iter().any(|(name, _)| name == family))
+    };
+    let fallback = PREFERRED_FAMILIES
+        .iter()
+        .find(|family| has_family(db, family))
+        .map(|family| family.to_string())
+        .or_else(|| {
+            db.faces()
+                .find_map(|face| face.families.first().map(|(name, _)| name.clone()))
+        });
+    if let Some(family) = fallback {
+        db.set_serif_family(family.clone());
+        db.set_sans_serif_family(family);
+    }
+}
@@ -0,0 +1,264 @@
+use handlebars::Handlebars;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::{io, path::Path};
+
+use super::assets::template_source;
+use super::output::OutputFormat;
+
+/// 注册到每个 [`CardTemplates`] 中的内置模板
+const BUILTIN_TEMPLATES: [&str; 3] = ["info_card", "info_grid", "info_compact"];
+
+/// 默认的星级配色，均匀分布在 0 ~ 10 星之间
+const DEFAULT_SR_COLORS: [(u8, u8, u8); 7] = [
+    (79, 192, 255),
+    (124, 255, 79),
+    (246, 240, 92),
+    (255, 78, 111),
+    (198, 69, 184),
+    (101, 99, 222),
+    (0, 0, 0),
+];
+
+/// 信息卡的排版方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum CardLayout {
+    /// 每个难度一张 1200x300 的卡片，纵向排列
+    #[default]
+    PerDifficulty,
+    /// 每行 `columns` 张 600x300 的卡片
+    Grid { columns: u32 },
+    /// 每个难度一行 1200x72 的紧凑列表
+    CompactList,
+}
+
+impl CardLayout {
+    /// 排版方式对应的内置模板名
+    pub fn template_name(&self) -> &'static str {
+        match self {
+            CardLayout::PerDifficulty => "info_card",
+            CardLayout::Grid { .. } => "info_grid",
+            CardLayout::CompactList => "info_compact",
+        }
+    }
+
+    /// 单张卡片的 (宽, 高)
+    pub fn card_size(&self) -> (u32, u32) {
+        match self {
+            CardLayout::PerDifficulty => (1200, 300),
+            CardLayout::Grid { .. } => (600, 300),
+            CardLayout::CompactList => (1200, 72),
+        }
+    }
+
+    /// 卡片之间的间隔
+    pub fn spacing(&self) -> u32 {
+        match self {
+            CardLayout::PerDifficulty => 0,
+            CardLayout::Grid { .. } => 10,
+            CardLayout::CompactList => 8,
+        }
+    }
+
+    /// 每行的卡片数
+    pub fn columns(&self) -> u32 {
+        match self {
+            CardLayout::Grid { columns } => (*columns).max(1),
+            _ => 1,
+        }
+    }
+
+    /// 第 `index` 张卡片左上角的坐标
+    pub fn card_offset(&self, index: usize) -> (u32, u32) {
+        let (width, height) = self.card_size();
+        let columns = self.columns() as usize;
+        let (row, column) = (index / columns, index % columns);
+        (
+            column as u32 * (width + self.spacing()),
+            row as u32 * (height + self.spacing()),
+        )
+    }
+
+    /// 容纳 `count` 张卡片的画布大小
+    pub fn canvas_size(&self, count: usize) -> (u32, u32) {
+        let (width, height) = self.card_size();
+        let columns = (self.columns() as usize).min(count.max(1));
+        let rows = count.max(1).div_ceil(self.columns() as usize);
+        (
+            columns as u32 * (width + self.spacing()) - self.spacing(),
+            rows as u32 * (height + self.spacing()) - self.spacing(),
+        )
+    }
+}
+
+/// 信息卡的配色，除星级配色外的字段会以 `theme` 传入模板
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct CardTheme {
+    /// 画布背景色
+    pub background: String,
+    /// 卡片底色
+    pub panel: String,
+    pub text: String,
+    pub secondary_text: String,
+    /// 文字描边色
+    pub stroke: String,
+    /// 强调色，如紧凑列表中的难度名
+    pub accent: String,
+    /// 星级配色，均匀分布在 0 ~ `sr_max` 之间
+    #[serde(skip)]
+    pub sr_colors: Vec<(u8, u8, u8)>,
+    #[serde(skip)]
+    pub sr_max: f64,
+}
+
+impl Default for CardTheme {
+    fn default() -> Self {
+        Self::dark()
+    }
+}
+
+impl CardTheme {
+    /// 默认的深色主题
+    pub fn dark() -> Self {
+        CardTheme {
+            background: "black".into(),
+            panel: "black".into(),
+            text: "white".into(),
+            secondary_text: "#ccc".into(),
+            stroke: "#000".into(),
+            accent: "rgb(120,200,255)".into(),
+            sr_colors: DEFAULT_SR_COLORS.to_vec(),
+            sr_max: 10.0,
+        }
+    }
+
+    /// 浅色主题
+    pub fn light() -> Self {
+        CardTheme {
+            background: "rgb(236,236,242)".into(),
+            panel: "white".into(),
+            text: "rgb(30,30,36)".into(),
+            secondary_text: "rgb(90,90,100)".into(),
+            stroke: "#fff".into(),
+            accent: "rgb(40,110,210)".into(),
+            sr_colors: DEFAULT_SR_COLORS.to_vec(),
+            sr_max: 10.0,
+        }
+    }
+
+    /// 星级对应的颜色，在相邻的两种颜色之间线性插值
+    pub fn sr_color(&self, sr: f64) -> String {
+        let (r, g, b) = match self.sr_colors.as_slice() {
+            [] => (0.0, 0.0, 0.0),
+            [c] => (c.0 as f64, c.1 as f64, c.2 as f64),
+            colors => {
+                let interval = self.sr_max.max(f64::EPSILON) / (colors.len() - 1) as f64;
+                let sr = sr.clamp(0.0, self.sr_max);
+                let section = ((sr / interval) as usize).min(colors.len() - 2);
+                let partial = (sr - interval * section as f64) / interval;
+                let (from, to) = (colors[section], colors[section + 1]);
+                let lerp = |a: u8, b: u8| a as f64 + (b as f64 - a as f64) * partial;
+                (lerp(from.0, to.0), lerp(from.1, to.1), lerp(from.2, to.2))
+            }
+        };
+        format!(
+            "rgb({},{},{})",
+            r.round() as u8,
+            g.round() as u8,
+            b.round() as u8
+        )
+    }
+}
+
+/// 生成信息卡的选项
+#[derive(Debug, Clone)]
+pub struct CardOptions {
+    pub layout: CardLayout,
+    pub theme: CardTheme,
+    /// 使用的模板名，为 None 时使用排版方式对应的内置模板
+    pub template: Option<String>,
+    /// 以 `extra` 传入模板的全局字段
+    pub extra: Map<String, Value>,
+    /// 与难度一一对应，以每张卡片的 `extra` 传入模板
+    pub card_extras: Vec<Value>,
+    pub format: OutputFormat,
+    /// 栅格化时的缩放倍数，1.0 时默认排版的宽度为 1200 像素
+    pub scale: f32,
+}
+
+impl Default for CardOptions {
+    fn default() -> Self {
+        CardOptions {
+            layout: CardLayout::default(),
+            theme: CardTheme::default(),
+            template: None,
+            extra: Map::new(),
+            card_extras: Vec::new(),
+            format: OutputFormat::Png,
+            scale: 1.0,
+        }
+    }
+}
+
+impl CardOptions {
+    pub fn template_name(&self) -> &str {
+        self.template
+            .as_deref()
+            .unwrap_or(self.layout.template_name())
+    }
+}
+
+/// 信息卡模板注册表，内置 "info_card"、"info_grid" 与 "info_compact" 三个模板，
+/// 也可以注册自定义的 Handlebars SVG 模板或覆盖内置模板。
+/// 内置模板编译进库中，可以通过 [`set_asset_dir`](super::set_asset_dir) 替换
+pub struct CardTemplates {
+    registry: Handlebars<'static>,
+}
+
+impl CardTemplates {
+    pub fn new() -> io::Result<Self> {
+        let mut templates = CardTemplates {
+            registry: Handlebars::new(),
+        };
+        for name in BUILTIN_TEMPLATES {
+            templates.register_template_string(name, &template_source(name)?)?;
+        }
+        Ok(templates)
+    }
+
+    /// 从文件注册模板，同名模板会被覆盖
+    pub fn register_template_file(&mut self, name: &str, path: &Path) -> io::Result<()> {
+        self.registry
+            .register_template_file(name, path)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    /// 从字符串注册模板，同名模板会被覆盖
+    pub fn register_template_string(&mut self, name: &str, template: &str) -> io::Result<()> {
+        self.registry
+            .register_template_string(name, template)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+
+    pub fn has_template(&self, name: &str) -> bool {
+        self.registry.has_template(name)
+    }
+
+    pub fn template_names(&self) -> Vec<String> {
+        let mut names: Vec<String> = self.registry.get_templates().keys().cloned().collect();
+        names.sort();
+        names
+    }
+
+    pub fn render<T: Serialize>(&self, name: &str, context: &T) -> io::Result<String> {
+        if !self.has_template(name) {
+            return Err(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("Template not found: {}", name),
+            ));
+        }
+        self.registry
+            .render(name, context)
+            .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+    }
+}
@@ -0,0 +1,223 @@
+use serde_json::json;
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use super::assets::render_template;
+use super::strain_graph::format_time_label;
+use super::{chart_pic_path, chart_title, save_svg, ChartSource};
+use crate::osu_func::OsuDataLegacy;
+
+/// 每毫秒对应的像素数
+const PX_PER_MS: f64 = 0.1;
+/// 每一条的高度（像素），即每条显示 8 秒
+const STRIP_HEIGHT: f64 = 800.0;
+const COLUMN_WIDTH: f64 = 10.0;
+const NOTE_HEIGHT: f64 = 4.0;
+/// 两条之间的间隔，用于显示 BPM
+const STRIP_GAP: f64 = 40.0;
+const MARGIN: f64 = 20.0;
+const HEADER_HEIGHT: f64 = 60.0;
+const FOOTER_HEIGHT: f64 = 30.0;
+
+#[derive(serde::Serialize)]
+struct Strip {
+    x: f64,
+    width: f64,
+    label_x: f64,
+    label: String,
+}
+
+#[derive(serde::Serialize)]
+struct NoteRect {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    color: &'static str,
+}
+
+#[derive(serde::Serialize)]
+struct Marker {
+    x1: f64,
+    x2: f64,
+    y: f64,
+    color: &'static str,
+    label: String,
+}
+
+/// 列的颜色：奇数键中间列为黄色，其余从外向内白蓝交替
+fn column_color(column: u32, column_count: u32) -> &'static str {
+    let from_edge = column.min(column_count - 1 - column);
+    if column_count % 2 == 1 && column == column_count / 2 {
+        "rgb(255,210,110)"
+    } else if from_edge % 2 == 1 {
+        "rgb(100,170,255)"
+    } else {
+        "rgb(235,235,235)"
+    }
+}
+
+/// 渲染类似 osu! 编辑器总览的谱面预览，返回 (SVG, 宽, 高)。
+/// 谱面按时间切分为多条，每条自下而上，条与条从左到右排列；红线标注 BPM，绿线为变速
+fn render_preview(data: &OsuDataLegacy, title: &str) -> io::Result<(String, u32, u32)> {
+    let column_count = data.misc.circle_size.max(1);
+    let end_time = data
+        .notes
+        .iter()
+        .map(|n| n.end_time.unwrap_or(n.time).max(n.time))
+        .max()
+        .unwrap_or(0) as f64;
+    let strip_duration = STRIP_HEIGHT / PX_PER_MS;
+    let strip_count = ((end_time + 1.0) / strip_duration).ceil().max(1.0) as usize;
+    let strip_width = column_count as f64 * COLUMN_WIDTH;
+    let strip_x = |i: usize| MARGIN + STRIP_GAP + i as f64 * (strip_width + STRIP_GAP);
+    let strip_bottom = HEADER_HEIGHT + STRIP_HEIGHT;
+    // 时刻所在的条及其纵坐标
+    let locate = |t: f64| {
+        let i = ((t / strip_duration).floor().max(0.0) as usize).min(strip_count - 1);
+        (
+            i,
+            strip_bottom - (t - i as f64 * strip_duration) * PX_PER_MS,
+        )
+    };
+
+    let strips: Vec<Strip> = (0..strip_count)
+        .map(|i| Strip {
+            x: strip_x(i),
+            width: strip_width,
+            label_x: strip_x(i) + strip_width / 2.0,
+            label: format_time_label(i as f64 * strip_duration),
+        })
+        .collect();
+
+    let mut notes: Vec<NoteRect> = Vec::with_capacity(data.notes.len());
+    for note in &data.notes {
+        let column = (note.x_pos * column_count / 512).min(column_count - 1);
+        let color = column_color(column, column_count);
+        let x = |i: usize| strip_x(i) + column as f64 * COLUMN_WIDTH + 1.0;
+        let start = note.time as f64;
+        match note.end_time.filter(|&e| e > note.time) {
+            Some(end) => {
+                // 跨越多条的面条按条拆分
+                let mut t = start;
+                while t < end as f64 {
+                    let (i, y_start) = locate(t);
+                    let piece_end = (end as f64).min((i + 1) as f64 * strip_duration);
+                    let y_end = strip_bottom - (piece_end - i as f64 * strip_duration) * PX_PER_MS;
+                    notes.push(NoteRect {
+                        x: x(i) + 1.5,
+                        y: y_end,
+                        width: COLUMN_WIDTH - 5.0,
+                        height: (y_start - y_end).max(NOTE_HEIGHT),
+                        color,
+                    });
+                    if piece_end <= t {
+                        break;
+                    }
+                    t = piece_end;
+                }
+                let (i, y) = locate(start);
+                notes.push(NoteRect {
+                    x: x(i),
+                    y: y - NOTE_HEIGHT,
+                    width: COLUMN_WIDTH - 2.0,
+                    height: NOTE_HEIGHT,
+                    color,
+                });
+            }
+            None => {
+                let (i, y) = locate(start);
+                notes.push(NoteRect {
+                    x: x(i),
+                    y: y - NOTE_HEIGHT,
+                    width: COLUMN_WIDTH - 2.0,
+                    height: NOTE_HEIGHT,
+                    color,
+                });
+            }
+        }
+    }
+
+    // 时间点标记，同一时刻的红线在绿线之后绘制
+    let mut timings: Vec<_> = data.timings.iter().filter(|t| t.time <= end_time).collect();
+    timings.sort_by(|a, b| {
+        a.time
+            .total_cmp(&b.time)
+            .then(a.is_timing.cmp(&b.is_timing))
+    });
+    let markers: Vec<Marker> = timings
+        .iter()
+        .map(|t| {
+            let (i, y) = locate(t.time.max(0.0));
+            let x = strip_x(i);
+            if t.is_timing {
+                Marker {
+                    x1: x - 4.0,
+                    x2: x + strip_width,
+                    y,
+                    color: "rgb(255,80,80)",
+                    label: format!("{:.0}", 60000.0 / t.val),
+                }
+            } else {
+                Marker {
+                    x1: x,
+                    x2: x + strip_width,
+                    y,
+                    color: "rgb(80,220,120)",
+                    label: String::new(),
+                }
+            }
+        })
+        .collect();
+
+    let width = (strip_x(strip_count) + MARGIN - STRIP_GAP).ceil() as u32;
+    let height = (strip_bottom + FOOTER_HEIGHT).ceil() as u32;
+    let svg_content = render_template(
+        "chart_preview",
+        &json!({
+            "width": width,
+            "height": height,
+            "title": title,
+            "title_x": MARGIN,
+            "strip_top": HEADER_HEIGHT,
+            "strip_height": STRIP_HEIGHT,
+            "label_y": strip_bottom + 20.0,
+            "strips": strips,
+            "notes": notes,
+            "markers": markers,
+        }),
+    )?;
+    Ok((svg_content, width, height))
+}
+
+/// 将谱面预览渲染为 SVG 字符串
+pub fn render_chart_preview_svg<C: ChartSource + ?Sized>(
+    chart: &C,
+    title: &str,
+) -> io::Result<String> {
+    render_preview(&*chart.chart_data()?, title).map(|(svg, _, _)| svg)
+}
+
+/// 将谱面预览保存到 `pic_path`
+pub fn save_chart_preview<C: ChartSource + ?Sized>(
+    chart: &C,
+    title: &str,
+    pic_path: &Path,
+) -> io::Result<()> {
+    let (svg_content, width, height) = render_preview(&*chart.chart_data()?, title)?;
+    save_svg(&svg_content, width, height, pic_path)
+}
+
+/// 在 `save_pic_path` 下生成谱面预览，文件名为 "标题 [难度名]_preview.png"
+pub fn generate_chart_preview<C: ChartSource + ?Sized>(
+    chart: &C,
+    save_pic_path: &Path,
+) -> io::Result<PathBuf> {
+    let data = chart.chart_data()?;
+    let title = chart_title(&data.misc);
+    let pic_path = chart_pic_path(&data.misc, save_pic_path, "_preview");
+    save_chart_preview(data.as_ref(), &title, &pic_path)?;
+    Ok(pic_path)
+}
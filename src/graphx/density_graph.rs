@@ -0,0 +1,166 @@
+use serde_json::json;
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use super::assets::render_template;
+use super::strain_graph::{format_time_label, nice_step, time_step};
+use super::{chart_pic_path, chart_title, save_svg, ChartSource};
+use crate::osu_func::{calculate_chart_stats, OsuDataLegacy};
+
+const GRAPH_WIDTH: u32 = 1200;
+const GRAPH_HEIGHT: u32 = 380;
+const PLOT_LEFT: f64 = 70.0;
+const PLOT_RIGHT: f64 = 1170.0;
+const PLOT_TOP: f64 = 60.0;
+const PLOT_BOTTOM: f64 = 330.0;
+/// 计算 NPS 的窗口长度（毫秒）
+const NPS_WINDOW: f64 = 1000.0;
+/// 曲线的目标采样点数
+const CURVE_SAMPLES: f64 = 400.0;
+
+#[derive(serde::Serialize)]
+struct Tick {
+    x: f64,
+    y: f64,
+    label: String,
+}
+
+/// 以 `step` 为间隔采样的 NPS 曲线，返回 (时刻, 全部音符 NPS, 面条 NPS)
+pub fn density_curve(data: &OsuDataLegacy, step: f64) -> Vec<(f64, f64, f64)> {
+    let mut starts: Vec<u32> = data.notes.iter().map(|n| n.time).collect();
+    let mut ln_starts: Vec<u32> = data
+        .notes
+        .iter()
+        .filter(|n| n.end_time.is_some_and(|e| e > n.time))
+        .map(|n| n.time)
+        .collect();
+    starts.sort_unstable();
+    ln_starts.sort_unstable();
+    let end_time = data
+        .notes
+        .iter()
+        .map(|n| n.end_time.unwrap_or(n.time).max(n.time))
+        .max()
+        .unwrap_or(0) as f64;
+
+    // 以采样点为中心的窗口内的音符数
+    let count_in = |times: &[u32], t: f64| {
+        let low = times.partition_point(|&x| (x as f64) < t - NPS_WINDOW / 2.0);
+        let high = times.partition_point(|&x| (x as f64) < t + NPS_WINDOW / 2.0);
+        (high - low) as f64 * 1000.0 / NPS_WINDOW
+    };
+    let step = step.max(1.0);
+    (0..=((end_time / step).ceil() as u32))
+        .map(|i| {
+            let t = i as f64 * step;
+            (t, count_in(&starts, t), count_in(&ln_starts, t))
+        })
+        .collect()
+}
+
+/// 将谱面的 NPS 曲线渲染为 SVG 字符串，面条部分单独叠加显示
+pub fn render_density_graph_svg<C: ChartSource + ?Sized>(
+    chart: &C,
+    title: &str,
+) -> io::Result<String> {
+    let data = chart.chart_data()?;
+    let end_time = data
+        .notes
+        .iter()
+        .map(|n| n.end_time.unwrap_or(n.time).max(n.time))
+        .max()
+        .unwrap_or(0)
+        .max(1) as f64;
+    let curve = density_curve(&data, (end_time / CURVE_SAMPLES).max(50.0));
+    let stats = calculate_chart_stats(&data);
+
+    let peak = curve.iter().map(|&(_, nps, _)| nps).fold(0.0, f64::max);
+    let y_step = nice_step((peak * 1.1).max(1.0) / 4.0);
+    let y_max = (peak * 1.1 / y_step).ceil().max(1.0) * y_step;
+
+    let x_of = |t: f64| PLOT_LEFT + t / end_time * (PLOT_RIGHT - PLOT_LEFT);
+    let y_of = |v: f64| PLOT_BOTTOM - v / y_max * (PLOT_BOTTOM - PLOT_TOP);
+    let area = |values: Vec<(f64, f64)>| {
+        let points: Vec<String> = values
+            .iter()
+            .map(|&(t, v)| format!("{:.1},{:.1}", x_of(t), y_of(v)))
+            .collect();
+        format!(
+            "{:.1},{:.1} {} {:.1},{:.1}",
+            x_of(values.first().map_or(0.0, |v| v.0)),
+            PLOT_BOTTOM,
+            points.join(" "),
+            x_of(values.last().map_or(end_time, |v| v.0)),
+            PLOT_BOTTOM
+        )
+    };
+    let note_area = area(curve.iter().map(|&(t, nps, _)| (t, nps)).collect());
+    let ln_area = area(curve.iter().map(|&(t, _, ln)| (t, ln)).collect());
+
+    let y_ticks: Vec<Tick> = (0..=((y_max / y_step).round() as u32))
+        .map(|i| {
+            let v = i as f64 * y_step;
+            Tick {
+                x: PLOT_LEFT,
+                y: y_of(v),
+                label: format!("{}", (v * 100.0).round() / 100.0),
+            }
+        })
+        .collect();
+    let x_step = time_step(end_time);
+    let x_ticks: Vec<Tick> = (0..=((end_time / x_step).floor() as u32))
+        .map(|i| {
+            let t = i as f64 * x_step;
+            Tick {
+                x: x_of(t),
+                y: PLOT_BOTTOM,
+                label: format_time_label(t),
+            }
+        })
+        .collect();
+
+    render_template(
+        "density_graph",
+        &json!({
+            "width": GRAPH_WIDTH,
+            "height": GRAPH_HEIGHT,
+            "plot_left": PLOT_LEFT,
+            "plot_right": PLOT_RIGHT,
+            "plot_top": PLOT_TOP,
+            "plot_bottom": PLOT_BOTTOM,
+            "label_x": PLOT_LEFT - 10.0,
+            "label_y": PLOT_BOTTOM + 20.0,
+            "title": title,
+            "subtitle": format!("NPS {:.2} avg / {:.2} peak", stats.average_nps, stats.peak_nps),
+            "note_area": note_area,
+            "ln_area": ln_area,
+            "average_y": y_of(stats.average_nps),
+            "x_ticks": x_ticks,
+            "y_ticks": y_ticks,
+        }),
+    )
+}
+
+/// 将 NPS 曲线图保存到 `pic_path`
+pub fn save_density_graph<C: ChartSource + ?Sized>(
+    chart: &C,
+    title: &str,
+    pic_path: &Path,
+) -> io::Result<()> {
+    let svg_content = render_density_graph_svg(chart, title)?;
+    save_svg(&svg_content, GRAPH_WIDTH, GRAPH_HEIGHT, pic_path)
+}
+
+/// 在 `save_pic_path` 下生成 NPS 曲线图，文件名为 "标题 [难度名]_density.png"
+pub fn generate_density_graph<C: ChartSource + ?Sized>(
+    chart: &C,
+    save_pic_path: &Path,
+) -> io::Result<PathBuf> {
+    let data = chart.chart_data()?;
+    let title = chart_title(&data.misc);
+    let pic_path = chart_pic_path(&data.misc, save_pic_path, "_density");
+    save_density_graph(data.as_ref(), &title, &pic_path)?;
+    Ok(pic_path)
+}
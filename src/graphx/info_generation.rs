@@ -1,38 +1,31 @@
-use handlebars::Handlebars;
-use lazy_static::lazy_static;
-use resvg::{tiny_skia, usvg};
-use serde_json::json;
+use serde_json::{json, Value};
 use std::{
-    env, fs, io,
+    fs, io,
     path::{Path, PathBuf},
-    sync::Arc,
 };
 
+use super::assets::{image_data_uri, no_image_href};
+use super::card_template::{CardOptions, CardTemplates, CardTheme};
+use super::output::{encode_svg, OutputFormat};
+use crate::malody_func::format_level;
 use crate::misc::sanitize_filename;
+use crate::osu_func::PpFormula;
 use crate::BeatMapInfo;
 
-const INFO_TEMPLATE_PATH: &str = "./svg/info_card.svg";
-const NO_IMAGE_PATH: &str = "./svg/no_image.jpg";
-const FONT_DIR_PATH: &str = "./font";
-const CARD_HEIGHT: u32 = 300;
-
-lazy_static! {
-    static ref FONTS: Arc<usvg::fontdb::Database> = {
-        // 使用嵌入的字体资源初始化字体数据库
-        let mut fontdb_origin = usvg::fontdb::Database::new();
-        fontdb_origin.load_fonts_dir(FONT_DIR_PATH);
-        Arc::new(fontdb_origin)
-    };
+#[derive(serde::Serialize)]
+struct PpEntry {
+    acc: f64,
+    pp: String,
+}
 
-    static ref HANDLEBARS: handlebars::Handlebars<'static> = {
-        let mut reg = Handlebars::new();
-        reg.register_template_file("template", INFO_TEMPLATE_PATH).expect("Failed to register template");
-        reg
-    };
+#[derive(serde::Serialize)]
+struct SkillEntry {
+    name: &'static str,
+    value: String,
 }
 
 #[derive(serde::Serialize)]
-struct CardData {
+struct CardData<'a> {
     bg_image: String,
     title_ascii: String,
     title: String,
@@ -45,17 +38,143 @@ struct CardData {
     length: String,
     sr_gradient: String,
     sr: String,
+    /// Malody 等级，如 "Lv.23"；由星级估计时为 "≈Lv.23"，osu! 谱面为空
+    level: String,
     note_str: String,
     ln_str: String,
+    skillset_str: String,
+    stats_str: String,
     len_pos: u32,
+    x_offset: u32,
     y_offset: u32,
+    width: u32,
+    height: u32,
+    /// 紧凑列表中文字的起始横坐标
+    text_x: u32,
+    /// 星级标签的横坐标
+    sr_x: u32,
+    /// 当前公式下的 pp 表
+    pp: Vec<PpEntry>,
+    /// 从高到低排列的技能评分（仅 4K）
+    skillsets: Vec<SkillEntry>,
+    /// 完整的谱面信息，自定义模板可以通过 `info.stats` 等访问
+    info: &'a BeatMapInfo,
+    extra: &'a Value,
 }
 
+/// 使用默认模板与选项生成信息卡，每个难度一张卡片
 pub fn generate_info_abstract(
     info_vec: &[BeatMapInfo],
     temp_dir_path: &Path,
     save_pic_path: &Path,
 ) -> io::Result<PathBuf> {
+    generate_info_cards(
+        info_vec,
+        temp_dir_path,
+        save_pic_path,
+        &CardTemplates::new()?,
+        &CardOptions::default(),
+    )
+}
+
+/// 按选项中的排版、配色、模板与输出格式生成信息卡，
+/// 文件名见 [`card_file_name`]，扩展名由输出格式决定
+pub fn generate_info_cards(
+    info_vec: &[BeatMapInfo],
+    temp_dir_path: &Path,
+    save_pic_path: &Path,
+    templates: &CardTemplates,
+    options: &CardOptions,
+) -> io::Result<PathBuf> {
+    let content = render_info_cards(info_vec, temp_dir_path, templates, options)?;
+
+    // 确保输出目录存在
+    fs::create_dir_all(save_pic_path)?;
+
+    let pic_name = format!(
+        "{}.{}",
+        card_file_name(info_vec),
+        options.format.extension()
+    );
+    let pic_path = save_pic_path.join(pic_name);
+    fs::write(&pic_path, content)?;
+
+    Ok(pic_path)
+}
+
+/// 按选项中的输出格式与缩放倍数生成信息卡，返回图片内容而不写入文件
+pub fn render_info_cards(
+    info_vec: &[BeatMapInfo],
+    temp_dir_path: &Path,
+    templates: &CardTemplates,
+    options: &CardOptions,
+) -> io::Result<Vec<u8>> {
+    let embed_images = options.format == OutputFormat::Svg;
+    let (svg_content, width, height) =
+        render_cards(info_vec, temp_dir_path, templates, options, embed_images)?;
+    encode_svg(
+        &svg_content,
+        temp_dir_path,
+        width,
+        height,
+        options.format,
+        options.scale,
+    )
+}
+
+/// 信息卡的文件名（不含扩展名）。有谱面集 ID 时为 "ID 标题"，
+/// 否则为 "标题 哈希"，哈希由元数据与难度名计算，标题相同的不同谱面集不会互相覆盖
+pub fn card_file_name(info_vec: &[BeatMapInfo]) -> String {
+    let Some(first) = info_vec.first() else {
+        return "empty".into();
+    };
+    if let Some(set_id) = info_vec
+        .iter()
+        .map(|info| info.beatmap_set_id)
+        .find(|&id| id > 0)
+    {
+        return sanitize_filename(&format!("{} {}", set_id, first.title));
+    }
+    let mut versions: Vec<&str> = info_vec.iter().map(|info| info.version.as_str()).collect();
+    versions.sort_unstable();
+    let digest = md5::compute(format!(
+        "{}\n{}\n{}\n{}",
+        first.title,
+        first.artist,
+        first.creator,
+        versions.join("\n")
+    ));
+    sanitize_filename(&format!("{} {}", first.title, &format!("{:x}", digest)[..8]))
+}
+
+/// 将信息卡渲染为 SVG 字符串，`temp_dir_path` 为背景图片所在的目录。
+/// 背景图以 data URI 嵌入，生成的 SVG 不依赖该目录
+pub fn render_info_cards_svg(
+    info_vec: &[BeatMapInfo],
+    temp_dir_path: &Path,
+    templates: &CardTemplates,
+    options: &CardOptions,
+) -> io::Result<String> {
+    render_cards(info_vec, temp_dir_path, templates, options, true).map(|(svg, _, _)| svg)
+}
+
+/// `embed_images` 为 true 时背景图以 data URI 嵌入，否则引用 `temp_dir_path` 中的文件
+fn render_cards(
+    info_vec: &[BeatMapInfo],
+    temp_dir_path: &Path,
+    templates: &CardTemplates,
+    options: &CardOptions,
+    embed_images: bool,
+) -> io::Result<(String, u32, u32)> {
+    if info_vec.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "No beatmap to generate info card",
+        ));
+    }
+    let layout = options.layout;
+    let theme = &options.theme;
+    let (card_width, card_height) = layout.card_size();
     let card_vec: Vec<CardData> = info_vec
         .iter()
         .enumerate()
@@ -65,13 +184,15 @@ pub fn generate_info_abstract(
                 None => "",
             };
             let bg_path = temp_dir_path.join(Path::new(bg_name));
-            let default_path = env::current_dir().unwrap().join(Path::new(NO_IMAGE_PATH));
-            let final_path = if bg_path.exists() {
-                bg_path
+            // 没有背景图时使用占位图。
+            // 输出 SVG 时背景图所在的临时目录随后会被删除，因此直接嵌入图片
+            let bg_path_string = if !bg_path.is_file() {
+                no_image_href()
+            } else if embed_images {
+                image_data_uri(&bg_path).unwrap_or_else(|_| no_image_href())
             } else {
-                default_path
+                bg_path.to_string_lossy().into_owned()
             };
-            let bg_path_string = final_path.to_string_lossy().into_owned();
 
             let title = info.title_unicode.as_ref().unwrap_or(&info.title);
             let title_ascii: &str = if &info.title == title {
@@ -89,6 +210,11 @@ pub fn generate_info_abstract(
             let delta_len = bpm_str.len() as u32 * 12;
             let length_str = format_length_str(info.length);
             let sr = info.sr.unwrap_or(0.0);
+            let level = match (info.malody_level, info.estimated_malody_level()) {
+                (Some(level), _) => format!("Lv.{}", level),
+                (None, Some(level)) if info.from_malody => format!("≈{}", format_level(level)),
+                _ => String::new(),
+            };
 
             let total_count = info.note_count + info.ln_count;
             let note_str = format!(
@@ -103,6 +229,55 @@ pub fn generate_info_abstract(
                 total_count
             );
 
+            // 只显示最高的两项技能
+            let skillset_str = info.skillsets.map_or(String::new(), |s| {
+                let top: Vec<String> = s
+                    .sorted()
+                    .iter()
+                    .take(2)
+                    .map(|(name, v)| format!("{} {:.2}", name, v))
+                    .collect();
+                format!("MSD {:.2} · {}", s.overall, top.join(" · "))
+            });
+
+            let stats_str = info.stats.as_ref().map_or(String::new(), |s| {
+                let mut items = vec![
+                    format!("NPS {:.1} (peak {:.0})", s.average_nps, s.peak_nps),
+                    format!(
+                        "Drain {}:{:02}",
+                        s.drain_time / 60000,
+                        (s.drain_time % 60000) / 1000
+                    ),
+                ];
+                if s.ln_coverage > 0.0 {
+                    items.push(format!("LN {:.0}%", s.ln_coverage * 100.0));
+                }
+                if let Some(bpm) = s.dominant_bpm.filter(|_| info.max_bpm.is_some()) {
+                    items.push(format!("Main BPM {:.0}", bpm));
+                }
+                items.join(" · ")
+            });
+
+            let pp = info.pp_attributes().map_or(Vec::new(), |attr| {
+                attr.pp_table(PpFormula::Current)
+                    .into_iter()
+                    .map(|(acc, pp)| PpEntry {
+                        acc,
+                        pp: format!("{:.0}", pp),
+                    })
+                    .collect()
+            });
+            let skillsets = info.skillsets.map_or(Vec::new(), |s| {
+                s.sorted()
+                    .into_iter()
+                    .map(|(name, v)| SkillEntry {
+                        name,
+                        value: format!("{:.2}", v),
+                    })
+                    .collect()
+            });
+            let (x_offset, y_offset) = layout.card_offset(i);
+
             CardData {
                 bg_image: bg_path_string,
                 title_ascii: title_ascii.into(),
@@ -114,58 +289,58 @@ pub fn generate_info_abstract(
                 column_count: info.column_count,
                 bpm: bpm_str,
                 length: length_str,
-                sr_gradient: format_sr_gradient(sr),
+                sr_gradient: theme.sr_color(sr),
                 sr: format!("{:.02}", sr),
+                level,
                 note_str: note_str,
                 ln_str: ln_str,
+                skillset_str,
+                stats_str,
                 len_pos: 190 + delta_len,
-                y_offset: i as u32 * CARD_HEIGHT,
+                x_offset,
+                y_offset,
+                width: card_width,
+                height: card_height,
+                text_x: card_height + 20,
+                sr_x: card_width.saturating_sub(135),
+                pp,
+                skillsets,
+                info,
+                extra: options.card_extras.get(i).unwrap_or(&Value::Null),
             }
         })
         .collect();
     // 渲染SVG
-    let total_height = card_vec.len() as u32 * CARD_HEIGHT;
-    let svg_content = HANDLEBARS
-        .render(
-            "template",
-            &json!({
-                "total_height": total_height,
-                "cards": card_vec
-            }),
-        )
-        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-
-    // 渲染选项
-    let options = usvg::Options {
-        fontdb: FONTS.clone(),
-        resources_dir: Some(temp_dir_path.to_path_buf()),
-        ..Default::default()
-    };
-
-    // 解析并渲染SVG
-    let tree = usvg::Tree::from_str(&svg_content, &options)
-        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
-
-    let mut pixmap = tiny_skia::Pixmap::new(1200, total_height)
-        .ok_or_else(|| io::Error::new(io::ErrorKind::Other, "Failed to create pixmap"))?;
-
-    resvg::render(&tree, tiny_skia::Transform::default(), &mut pixmap.as_mut());
-
-    // 确保输出目录存在
-    if let Some(parent) = save_pic_path.parent() {
-        fs::create_dir_all(parent)?;
-    }
-
-    // 保存为PNG
-    let santized_name = sanitize_filename(&info_vec[0].title);
-    let pic_name = format!("{}.png", santized_name);
-    let pic_path = save_pic_path.join(pic_name);
-
-    pixmap
-        .save_png(&pic_path)
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
+    let (total_width, total_height) = layout.canvas_size(card_vec.len());
+    let svg_content = templates.render(
+        options.template_name(),
+        &json!({
+            "total_width": total_width,
+            "total_height": total_height,
+            "card_width": card_width,
+            "card_height": card_height,
+            "theme": theme,
+            "extra": options.extra,
+            "cards": card_vec
+        }),
+    )?;
+    Ok((svg_content, total_width, total_height))
+}
 
-    Ok(pic_path)
+/// 使用 resvg 将 SVG 栅格化并保存，格式由扩展名决定（默认 PNG），`resources_dir` 用于解析图片等相对路径
+pub(crate) fn render_svg_to_png(
+    svg_content: &str,
+    resources_dir: &Path,
+    width: u32,
+    height: u32,
+    pic_path: &Path,
+) -> io::Result<()> {
+    let format = match OutputFormat::from_path(pic_path) {
+        Some(OutputFormat::Svg) | None => OutputFormat::Png,
+        Some(format) => format,
+    };
+    let content = encode_svg(svg_content, resources_dir, width, height, format, 1.0)?;
+    fs::write(pic_path, content)
 }
 
 fn format_bpm_str(min_bpm: f64, max_bpm: Option<f64>) -> String {
@@ -196,28 +371,7 @@ fn format_length_str(length: u32) -> String {
     format!("{}:{:02}.{:03}", mins, secs, msecs)
 }
 
-fn format_sr_gradient(sr: f64) -> String {
-    let colors = [
-        (79.0, 192.0, 255.0),
-        (124.0, 255.0, 79.0),
-        (246.0, 240.0, 92.0),
-        (255.0, 78.0, 111.0),
-        (198.0, 69.0, 184.0),
-        (101.0, 99.0, 222.0),
-        (0.0, 0.0, 0.0),
-        (0.0, 0.0, 0.0),
-    ];
-    let sr = sr.clamp(0.0, 10.0);
-    let interval = 10.0 / (colors.len() - 2) as f64;
-    let section = (sr / interval) as usize;
-    let partial = (sr - interval * section as f64) / interval;
-    let r = colors[section].0 + (colors[section + 1].0 - colors[section].0) * partial;
-    let g = colors[section].1 + (colors[section + 1].1 - colors[section].1) * partial;
-    let b = colors[section].2 + (colors[section + 1].2 - colors[section].2) * partial;
-    format!(
-        "rgb({},{},{})",
-        r.round() as u8,
-        g.round() as u8,
-        b.round() as u8
-    )
+/// 默认主题下星级对应的颜色
+pub(crate) fn format_sr_gradient(sr: f64) -> String {
+    CardTheme::dark().sr_color(sr)
 }
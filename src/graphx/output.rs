@@ -0,0 +1,145 @@
+use image::{codecs, ExtendedColorType, ImageEncoder};
+use resvg::{tiny_skia, usvg};
+use std::{io, path::Path};
+
+use super::assets::fonts;
+
+/// 图片的输出格式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum OutputFormat {
+    /// 不栅格化，直接输出 SVG 源码
+    Svg,
+    #[default]
+    Png,
+    /// 质量范围 1 ~ 100，透明部分以黑色填充
+    Jpeg { quality: u8 },
+    /// 无损 WebP
+    WebP,
+}
+
+impl OutputFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            OutputFormat::Svg => "svg",
+            OutputFormat::Png => "png",
+            OutputFormat::Jpeg { .. } => "jpg",
+            OutputFormat::WebP => "webp",
+        }
+    }
+
+    /// 由扩展名判断格式，JPEG 使用默认质量 90
+    pub fn from_extension(ext: &str) -> Option<Self> {
+        match ext.to_ascii_lowercase().as_str() {
+            "svg" => Some(OutputFormat::Svg),
+            "png" => Some(OutputFormat::Png),
+            "jpg" | "jpeg" => Some(OutputFormat::Jpeg { quality: 90 }),
+            "webp" => Some(OutputFormat::WebP),
+            _ => None,
+        }
+    }
+
+    pub fn from_path(path: &Path) -> Option<Self> {
+        Self::from_extension(path.extension()?.to_str()?)
+    }
+}
+
+/// 将 SVG 按 `scale` 倍栅格化，`resources_dir` 用于解析图片等相对路径
+pub(crate) fn rasterize(
+    svg_content: &str,
+    resources_dir: &Path,
+    width: u32,
+    height: u32,
+    scale: f32,
+) -> io::Result<tiny_skia::Pixmap> {
+    if !(scale.is_finite() && scale > 0.0) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid scale: {}", scale),
+        ));
+    }
+    // 渲染选项
+    let options = usvg::Options {
+        fontdb: fonts()?,
+        resources_dir: Some(resources_dir.to_path_buf()),
+        ..Default::default()
+    };
+
+    // 解析并渲染SVG
+    let tree = usvg::Tree::from_str(svg_content, &options)
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+
+    let pixel_width = (width as f32 * scale).round().max(1.0) as u32;
+    let pixel_height = (height as f32 * scale).round().max(1.0) as u32;
+    let mut pixmap = tiny_skia::Pixmap::new(pixel_width, pixel_height)
+        .ok_or_else(|| io::Error::other("Failed to create pixmap"))?;
+
+    resvg::render(
+        &tree,
+        tiny_skia::Transform::from_scale(scale, scale),
+        &mut pixmap.as_mut(),
+    );
+    Ok(pixmap)
+}
+
+/// 将栅格化的图片编码为指定格式
+pub(crate) fn encode_pixmap(
+    pixmap: &tiny_skia::Pixmap,
+    format: OutputFormat,
+) -> io::Result<Vec<u8>> {
+    let (width, height) = (pixmap.width(), pixmap.height());
+    let mut buffer = Vec::new();
+    match format {
+        OutputFormat::Svg => {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "SVG output does not need rasterization",
+            ))
+        }
+        OutputFormat::Png => {
+            buffer = pixmap.encode_png().map_err(io::Error::other)?;
+        }
+        OutputFormat::Jpeg { quality } => {
+            // 预乘后的颜色即为叠加在黑色背景上的结果
+            let rgb: Vec<u8> = pixmap
+                .pixels()
+                .iter()
+                .flat_map(|p| [p.red(), p.green(), p.blue()])
+                .collect();
+            codecs::jpeg::JpegEncoder::new_with_quality(&mut buffer, quality.clamp(1, 100))
+                .write_image(&rgb, width, height, ExtendedColorType::Rgb8)
+                .map_err(io::Error::other)?;
+        }
+        OutputFormat::WebP => {
+            let rgba: Vec<u8> = pixmap
+                .pixels()
+                .iter()
+                .flat_map(|p| {
+                    let c = p.demultiply();
+                    [c.red(), c.green(), c.blue(), c.alpha()]
+                })
+                .collect();
+            codecs::webp::WebPEncoder::new_lossless(&mut buffer)
+                .write_image(&rgba, width, height, ExtendedColorType::Rgba8)
+                .map_err(io::Error::other)?;
+        }
+    }
+    Ok(buffer)
+}
+
+/// 将 SVG 输出为指定格式的字节，SVG 格式直接返回源码
+pub(crate) fn encode_svg(
+    svg_content: &str,
+    resources_dir: &Path,
+    width: u32,
+    height: u32,
+    format: OutputFormat,
+    scale: f32,
+) -> io::Result<Vec<u8>> {
+    match format {
+        OutputFormat::Svg => Ok(svg_content.as_bytes().to_vec()),
+        _ => encode_pixmap(
+            &rasterize(svg_content, resources_dir, width, height, scale)?,
+            format,
+        ),
+    }
+}
@@ -0,0 +1,544 @@
+use serde_json::json;
+use std::cmp::Ordering;
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use super::assets::render_template;
+use super::strain_graph::{format_time_label, time_step};
+use super::{chart_pic_path, chart_title, save_svg, ChartSource};
+use crate::malody_func::McData;
+use crate::osu_func::{OsuDataLegacy, OsuTimingPoint};
+use crate::transform::sv::dominant_bpm;
+
+const GRAPH_WIDTH: u32 = 1200;
+const GRAPH_HEIGHT: u32 = 600;
+const PLOT_LEFT: f64 = 70.0;
+const PLOT_RIGHT: f64 = 1170.0;
+const PLOT_TOP: f64 = 60.0;
+const PLOT_BOTTOM: f64 = 540.0;
+/// 速度绝对值低于此值的区间视为停止
+const STOP_VELOCITY: f64 = 0.05;
+/// 速度高于此值的区间视为瞬移
+const TELEPORT_VELOCITY: f64 = 4.0;
+
+/// 每一帧中每列的宽度（像素）
+const FRAME_COLUMN_WIDTH: f64 = 16.0;
+const FRAME_HEIGHT: f64 = 480.0;
+const FRAME_GAP: f64 = 24.0;
+const FRAME_MARGIN: f64 = 20.0;
+const FRAME_HEADER: f64 = 60.0;
+const FRAME_FOOTER: f64 = 30.0;
+/// 判定线距离帧底部的距离
+const JUDGEMENT_OFFSET: f64 = 30.0;
+/// 基准速度下一帧内可见的时长（毫秒）
+const VISIBLE_DURATION: f64 = 1500.0;
+const NOTE_HEIGHT: f64 = 5.0;
+
+/// 模拟滚动所需的谱面信息，时刻均为毫秒
+#[derive(Debug, Clone, Default)]
+pub struct ScrollChart {
+    pub column_count: u32,
+    /// (列, 开始, 结束)，非面条的结束为 None
+    pub notes: Vec<(u32, f64, Option<f64>)>,
+    /// (时刻, 速度)，已包含 BPM 变化的影响，1 为基准速度，负数为倒退
+    pub velocities: Vec<(f64, f64)>,
+    /// 基准 BPM（持续时间最长的 BPM）
+    pub base_bpm: f64,
+}
+
+impl ScrollChart {
+    /// 由 osu! 的红线与绿线计算速度，红线会将变速重置为 1
+    pub fn from_osu(data: &OsuDataLegacy) -> Self {
+        let column_count = data.misc.circle_size.max(1);
+        let notes: Vec<(u32, f64, Option<f64>)> = data
+            .notes
+            .iter()
+            .map(|n| {
+                let column = (n.x_pos * column_count / 512).min(column_count - 1);
+                (
+                    column,
+                    n.time as f64,
+                    n.end_time.filter(|&e| e > n.time).map(|e| e as f64),
+                )
+            })
+            .collect();
+        let end_time = notes
+            .iter()
+            .map(|&(_, start, end)| end.unwrap_or(start))
+            .fold(0.0, f64::max);
+        let base_bpm = dominant_bpm(&data.timings, end_time).unwrap_or(120.0);
+
+        let mut timings: Vec<&OsuTimingPoint> = data.timings.iter().collect();
+        // 同一时刻红线在前，绿线在后覆盖
+        timings.sort_by(|a, b| {
+            a.time
+                .partial_cmp(&b.time)
+                .unwrap_or(Ordering::Equal)
+                .then(b.is_timing.cmp(&a.is_timing))
+        });
+        let mut bpm = base_bpm;
+        let mut sv = 1.0;
+        let velocities = timings
+            .iter()
+            .map(|t| {
+                if t.is_timing {
+                    bpm = 60000.0 / t.val;
+                    sv = 1.0;
+                } else if t.val < 0.0 {
+                    sv = -100.0 / t.val;
+                }
+                (t.time, sv * bpm / base_bpm)
+            })
+            .collect();
+
+        Self {
+            column_count,
+            notes,
+            velocities,
+            base_bpm,
+        }
+    }
+
+    /// 直接由 Malody 的 BPM 与变速效果计算速度，保留转换为 osu! 时会丢失的倒退与停止
+    pub fn from_mc(data: &McData) -> Self {
+        let column_count = (data.meta.mode_ext.column as u32).max(1);
+        let notes: Vec<(u32, f64, Option<f64>)> = data
+            .note
+            .iter()
+            .filter_map(|n| {
+                let column = (n.column? as u32).min(column_count - 1);
+                let start = data.beat_to_time(n.beat_to_float());
+                let end = n
+                    .endbeat
+                    .as_ref()
+                    .map(|_| data.beat_to_time(n.end_beat_to_float()))
+                    .filter(|&e| e > start);
+                Some((column, start, end))
+            })
+            .collect();
+        let end_time = notes
+            .iter()
+            .map(|&(_, start, end)| end.unwrap_or(start))
+            .fold(0.0, f64::max);
+
+        let red_lines: Vec<OsuTimingPoint> = data
+            .time
+            .iter()
+            .map(|t| OsuTimingPoint {
+                time: data.beat_to_time(t.beat_to_float()),
+                val: 60000.0 / t.bpm,
+                is_timing: true,
+            })
+            .collect();
+        let base_bpm = dominant_bpm(&red_lines, end_time).unwrap_or(120.0);
+
+        // (时刻, 新的 BPM, 新的变速)
+        let mut events: Vec<(f64, Option<f64>, Option<f64>)> = red_lines
+            .iter()
+            .map(|t| (t.time, Some(60000.0 / t.val), None))
+            .collect();
+        if let Some(effects) = &data.effect {
+            events.extend(
+                effects
+                    .iter()
+                    .map(|e| (data.beat_to_time(e.beat_to_float()), None, Some(e.scroll))),
+            );
+        }
+        events.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        let mut bpm = red_lines.first().map_or(base_bpm, |t| 60000.0 / t.val);
+        let mut scroll = 1.0;
+        let velocities = events
+            .into_iter()
+            .map(|(time, new_bpm, new_scroll)| {
+                bpm = new_bpm.unwrap_or(bpm);
+                scroll = new_scroll.unwrap_or(scroll);
+                (time, scroll * bpm / base_bpm)
+            })
+            .collect();
+
+        Self {
+            column_count,
+            notes,
+            velocities,
+            base_bpm,
+        }
+    }
+
+    pub fn end_time(&self) -> f64 {
+        self.notes
+            .iter()
+            .map(|&(_, start, end)| end.unwrap_or(start))
+            .fold(0.0, f64::max)
+    }
+
+    pub fn curve(&self) -> ScrollCurve {
+        ScrollCurve::new(&self.velocities)
+    }
+}
+
+/// 滚动位置曲线：位置为速度对时间的积分，基准速度下每毫秒前进 1
+#[derive(Debug, Clone, Default)]
+pub struct ScrollCurve {
+    /// (时刻, 该时刻的位置, 之后的速度)，按时刻排序
+    points: Vec<(f64, f64, f64)>,
+}
+
+impl ScrollCurve {
+    /// `velocities` 为 (时刻, 速度)，同一时刻以最后一项为准；第一项之前的速度为 1
+    pub fn new(velocities: &[(f64, f64)]) -> Self {
+        let mut sorted: Vec<(f64, f64)> = velocities.to_vec();
+        sorted.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        let mut points: Vec<(f64, f64, f64)> = Vec::with_capacity(sorted.len());
+        for (time, velocity) in sorted {
+            match points.last().copied() {
+                Some((t, _, _)) if t == time => {
+                    if let Some(last) = points.last_mut() {
+                        last.2 = velocity;
+                    }
+                }
+                Some((t, p, v)) => points.push((time, p + (time - t) * v, velocity)),
+                None => points.push((time, time, velocity)),
+            }
+        }
+        Self { points }
+    }
+
+    pub fn velocity_at(&self, time: f64) -> f64 {
+        let i = self.points.partition_point(|p| p.0 <= time);
+        if i == 0 {
+            1.0
+        } else {
+            self.points[i - 1].2
+        }
+    }
+
+    pub fn position_at(&self, time: f64) -> f64 {
+        let i = self.points.partition_point(|p| p.0 <= time);
+        if i == 0 {
+            time
+        } else {
+            let (t, p, v) = self.points[i - 1];
+            p + (time - t) * v
+        }
+    }
+
+    /// 曲线在 [start, end] 内的折点，包含两端
+    fn breakpoints(&self, start: f64, end: f64) -> Vec<(f64, f64)> {
+        let mut result = vec![(start, self.position_at(start))];
+        result.extend(
+            self.points
+                .iter()
+                .filter(|p| p.0 > start && p.0 < end)
+                .map(|p| (p.0, p.1)),
+        );
+        result.push((end, self.position_at(end)));
+        result
+    }
+
+    /// 速度满足条件的区间
+    fn ranges_where<F: Fn(f64) -> bool>(&self, end: f64, predicate: F) -> Vec<(f64, f64)> {
+        let mut ranges: Vec<(f64, f64)> = Vec::new();
+        for (i, &(t, _, v)) in self.points.iter().enumerate() {
+            let next = self.points.get(i + 1).map_or(end, |p| p.0);
+            if t >= end || next <= t || !predicate(v) {
+                continue;
+            }
+            match ranges.last_mut() {
+                Some(last) if last.1 == t => last.1 = next.min(end),
+                _ => ranges.push((t, next.min(end))),
+            }
+        }
+        ranges
+    }
+}
+
+#[derive(serde::Serialize)]
+struct Tick {
+    x: f64,
+    label: String,
+}
+
+#[derive(serde::Serialize)]
+struct Band {
+    x: f64,
+    width: f64,
+    color: &'static str,
+}
+
+#[derive(serde::Serialize)]
+struct Shape {
+    x: f64,
+    y: f64,
+    width: f64,
+    height: f64,
+    color: &'static str,
+}
+
+#[derive(serde::Serialize)]
+struct LnBody {
+    points: String,
+    color: &'static str,
+}
+
+fn column_color(column: u32, column_count: u32) -> &'static str {
+    const COLORS: [&str; 4] = [
+        "rgb(235,235,235)",
+        "rgb(100,170,255)",
+        "rgb(255,210,110)",
+        "rgb(255,130,180)",
+    ];
+    COLORS[(column.min(column_count - 1 - column) % 4) as usize]
+}
+
+/// 将滚动位置随时间的变化渲染为 SVG 字符串。
+/// 横轴为时间，纵轴为滚动位置；平台为停止，下降为倒退，近乎竖直的跳变为瞬移
+pub fn render_scroll_graph_svg<C: ChartSource + ?Sized>(
+    chart: &C,
+    title: &str,
+) -> io::Result<String> {
+    let scroll = chart.scroll_chart()?;
+    let curve = scroll.curve();
+    let end_time = scroll.end_time().max(1.0);
+    let breakpoints = curve.breakpoints(0.0, end_time);
+    let (min_pos, max_pos) = breakpoints
+        .iter()
+        .fold((f64::INFINITY, f64::NEG_INFINITY), |(lo, hi), &(_, p)| {
+            (lo.min(p), hi.max(p))
+        });
+    let span = (max_pos - min_pos).max(1.0);
+
+    let x_of = |t: f64| PLOT_LEFT + t / end_time * (PLOT_RIGHT - PLOT_LEFT);
+    let y_of = |p: f64| PLOT_BOTTOM - (p - min_pos) / span * (PLOT_BOTTOM - PLOT_TOP);
+    let line_points: Vec<String> = breakpoints
+        .iter()
+        .map(|&(t, p)| format!("{:.1},{:.1}", x_of(t), y_of(p)))
+        .collect();
+
+    let band = |range: (f64, f64), color| Band {
+        x: x_of(range.0),
+        width: (x_of(range.1) - x_of(range.0)).max(1.0),
+        color,
+    };
+    let mut bands: Vec<Band> = Vec::new();
+    bands.extend(
+        curve
+            .ranges_where(end_time, |v| v.abs() < STOP_VELOCITY)
+            .into_iter()
+            .map(|r| band(r, "rgb(160,160,170)")),
+    );
+    bands.extend(
+        curve
+            .ranges_where(end_time, |v| v <= -STOP_VELOCITY)
+            .into_iter()
+            .map(|r| band(r, "rgb(255,80,80)")),
+    );
+    bands.extend(
+        curve
+            .ranges_where(end_time, |v| v > TELEPORT_VELOCITY)
+            .into_iter()
+            .map(|r| band(r, "rgb(255,210,110)")),
+    );
+
+    let column_count = scroll.column_count;
+    let mut heads: Vec<Shape> = Vec::with_capacity(scroll.notes.len());
+    let mut lns: Vec<LnBody> = Vec::new();
+    for &(column, start, end) in &scroll.notes {
+        let color = column_color(column, column_count);
+        heads.push(Shape {
+            x: x_of(start) - 2.0,
+            y: y_of(curve.position_at(start)) - 2.0,
+            width: 4.0,
+            height: 4.0,
+            color,
+        });
+        if let Some(end) = end {
+            let points: Vec<String> = curve
+                .breakpoints(start, end)
+                .iter()
+                .map(|&(t, p)| format!("{:.1},{:.1}", x_of(t), y_of(p)))
+                .collect();
+            lns.push(LnBody {
+                points: points.join(" "),
+                color,
+            });
+        }
+    }
+
+    let x_step = time_step(end_time);
+    let x_ticks: Vec<Tick> = (0..=((end_time / x_step).floor() as u32))
+        .map(|i| {
+            let t = i as f64 * x_step;
+            Tick {
+                x: x_of(t),
+                label: format_time_label(t),
+            }
+        })
+        .collect();
+
+    render_template(
+        "scroll_graph",
+        &json!({
+            "width": GRAPH_WIDTH,
+            "height": GRAPH_HEIGHT,
+            "plot_left": PLOT_LEFT,
+            "plot_right": PLOT_RIGHT,
+            "plot_top": PLOT_TOP,
+            "plot_bottom": PLOT_BOTTOM,
+            "plot_height": PLOT_BOTTOM - PLOT_TOP,
+            "label_y": PLOT_BOTTOM + 20.0,
+            "legend_y": PLOT_BOTTOM + 35.0,
+            "title": title,
+            "subtitle": format!("Base BPM {:.1}", scroll.base_bpm),
+            "line_points": line_points.join(" "),
+            "bands": bands,
+            "heads": heads,
+            "lns": lns,
+            "x_ticks": x_ticks,
+        }),
+    )
+}
+
+/// 将 [start, end) 内每隔 `interval` 毫秒玩家看到的画面渲染为一排帧，返回 (SVG, 宽, 高)
+fn render_frames(
+    scroll: &ScrollChart,
+    title: &str,
+    start: f64,
+    end: f64,
+    interval: f64,
+) -> io::Result<(String, u32, u32)> {
+    if interval <= 0.0 || end <= start {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Frame range must be non-empty with a positive interval",
+        ));
+    }
+    let curve = scroll.curve();
+    let column_count = scroll.column_count;
+    let frame_width = column_count as f64 * FRAME_COLUMN_WIDTH;
+    let judgement_y = FRAME_HEADER + FRAME_HEIGHT - JUDGEMENT_OFFSET;
+    let scale = (FRAME_HEIGHT - JUDGEMENT_OFFSET) / VISIBLE_DURATION;
+    let frame_count = ((end - start) / interval).ceil() as usize;
+
+    let mut frames: Vec<Tick> = Vec::with_capacity(frame_count);
+    let mut notes: Vec<Shape> = Vec::new();
+    for i in 0..frame_count {
+        let time = start + i as f64 * interval;
+        let frame_x = FRAME_MARGIN + i as f64 * (frame_width + FRAME_GAP);
+        frames.push(Tick {
+            x: frame_x,
+            label: format_time_label(time),
+        });
+        let current = curve.position_at(time);
+        let y_of = |t: f64| judgement_y - (curve.position_at(t) - current) * scale;
+        let visible = |y: f64| (FRAME_HEADER..=FRAME_HEADER + FRAME_HEIGHT).contains(&y);
+        for &(column, note_start, note_end) in &scroll.notes {
+            // 已经判定过的音符不再显示
+            if note_end.unwrap_or(note_start) < time {
+                continue;
+            }
+            let x = frame_x + column as f64 * FRAME_COLUMN_WIDTH + 1.0;
+            let color = column_color(column, column_count);
+            if let Some(note_end) = note_end {
+                // 面条身体：按曲线折点分段，只绘制可见部分
+                let points = curve.breakpoints(note_start.max(time), note_end);
+                for pair in points.windows(2) {
+                    let (y0, y1) = (y_of(pair[0].0), y_of(pair[1].0));
+                    let top = y0.min(y1).max(FRAME_HEADER);
+                    let bottom = y0.max(y1).min(FRAME_HEADER + FRAME_HEIGHT);
+                    if bottom > top {
+                        notes.push(Shape {
+                            x: x + 3.0,
+                            y: top,
+                            width: FRAME_COLUMN_WIDTH - 8.0,
+                            height: bottom - top,
+                            color,
+                        });
+                    }
+                }
+            }
+            if note_start >= time {
+                let y = y_of(note_start);
+                if visible(y) {
+                    notes.push(Shape {
+                        x,
+                        y: y - NOTE_HEIGHT,
+                        width: FRAME_COLUMN_WIDTH - 2.0,
+                        height: NOTE_HEIGHT,
+                        color,
+                    });
+                }
+            }
+        }
+    }
+
+    let width = (FRAME_MARGIN * 2.0 + frame_count as f64 * (frame_width + FRAME_GAP) - FRAME_GAP)
+        .ceil() as u32;
+    let height = (FRAME_HEADER + FRAME_HEIGHT + FRAME_FOOTER).ceil() as u32;
+    let svg_content = render_template(
+        "scroll_frames",
+        &json!({
+            "width": width,
+            "height": height,
+            "title": title,
+            "title_x": FRAME_MARGIN,
+            "frame_top": FRAME_HEADER,
+            "frame_width": frame_width,
+            "frame_height": FRAME_HEIGHT,
+            "judgement_y": judgement_y,
+            "label_y": FRAME_HEADER + FRAME_HEIGHT + 20.0,
+            "label_dx": frame_width / 2.0,
+            "frames": frames,
+            "notes": notes,
+        }),
+    )?;
+    Ok((svg_content, width, height))
+}
+
+/// 将 [start, end) 内每隔 `interval` 毫秒玩家看到的画面渲染为一排帧
+pub fn render_scroll_frames_svg<C: ChartSource + ?Sized>(
+    chart: &C,
+    title: &str,
+    start: f64,
+    end: f64,
+    interval: f64,
+) -> io::Result<String> {
+    render_frames(&chart.scroll_chart()?, title, start, end, interval).map(|(svg, _, _)| svg)
+}
+
+/// 将滚动位置图保存到 `pic_path`
+pub fn save_scroll_graph<C: ChartSource + ?Sized>(
+    chart: &C,
+    title: &str,
+    pic_path: &Path,
+) -> io::Result<()> {
+    let svg_content = render_scroll_graph_svg(chart, title)?;
+    save_svg(&svg_content, GRAPH_WIDTH, GRAPH_HEIGHT, pic_path)
+}
+
+/// 将一排滚动画面帧保存到 `pic_path`
+pub fn save_scroll_frames<C: ChartSource + ?Sized>(
+    chart: &C,
+    title: &str,
+    start: f64,
+    end: f64,
+    interval: f64,
+    pic_path: &Path,
+) -> io::Result<()> {
+    let (svg_content, width, height) =
+        render_frames(&chart.scroll_chart()?, title, start, end, interval)?;
+    save_svg(&svg_content, width, height, pic_path)
+}
+
+/// 在 `save_pic_path` 下生成滚动位置图，文件名为 "标题 [难度名]_scroll.png"
+pub fn generate_scroll_graph<C: ChartSource + ?Sized>(
+    chart: &C,
+    save_pic_path: &Path,
+) -> io::Result<PathBuf> {
+    let data = chart.chart_data()?;
+    let title = chart_title(&data.misc);
+    let pic_path = chart_pic_path(&data.misc, save_pic_path, "_scroll");
+    save_scroll_graph(chart, &title, &pic_path)?;
+    Ok(pic_path)
+}
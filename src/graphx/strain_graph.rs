@@ -0,0 +1,194 @@
+use serde_json::json;
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+
+use super::assets::render_template;
+use super::info_generation::format_sr_gradient;
+use super::{chart_pic_path, chart_title, save_svg};
+use crate::osu_func::{calculate_curve, calculate_from_data, DifficultyCurve, OsuDataLegacy};
+
+const GRAPH_WIDTH: u32 = 1200;
+const GRAPH_HEIGHT: u32 = 410;
+const PLOT_LEFT: f64 = 70.0;
+const PLOT_RIGHT: f64 = 1170.0;
+const PLOT_TOP: f64 = 60.0;
+const PLOT_BOTTOM: f64 = 340.0;
+/// 曲线的目标采样点数
+const CURVE_SAMPLES: f64 = 400.0;
+
+#[derive(serde::Serialize)]
+struct Tick {
+    x: f64,
+    y: f64,
+    label: String,
+}
+
+#[derive(serde::Serialize)]
+struct BarLine {
+    name: &'static str,
+    color: &'static str,
+    points: String,
+    legend_x: f64,
+    text_x: f64,
+}
+
+/// 不小于 `raw` 的 1/2/5 × 10^n
+pub(super) fn nice_step(raw: f64) -> f64 {
+    let magnitude = 10f64.powf(raw.max(1e-9).log10().floor());
+    [1.0, 2.0, 5.0, 10.0]
+        .iter()
+        .map(|m| m * magnitude)
+        .find(|&s| s >= raw)
+        .unwrap_or(10.0 * magnitude)
+}
+
+pub(super) fn format_time_label(ms: f64) -> String {
+    let secs = (ms / 1000.0).round() as u32;
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+/// 时间轴的刻度间隔，刻度不超过 10 个
+pub(super) fn time_step(end_time: f64) -> f64 {
+    [10_000.0, 15_000.0, 30_000.0, 60_000.0, 120_000.0, 300_000.0]
+        .into_iter()
+        .find(|s| end_time / s <= 10.0)
+        .unwrap_or(600_000.0)
+}
+
+/// 将难度曲线渲染为 SVG 字符串。各项指标按各自的峰值归一化后叠加显示。
+pub fn render_strain_graph_svg(
+    curve: &DifficultyCurve,
+    title: &str,
+    sr: Option<f64>,
+) -> io::Result<String> {
+    let end_time = curve.times.last().copied().unwrap_or(0.0).max(1.0);
+    let peak = curve.difficulty.iter().copied().fold(0.0, f64::max);
+    let y_step = nice_step((peak * 1.1).max(1.0) / 4.0);
+    let y_max = (peak * 1.1 / y_step).ceil().max(1.0) * y_step;
+
+    let x_of = |t: f64| PLOT_LEFT + t / end_time * (PLOT_RIGHT - PLOT_LEFT);
+    let y_of = |v: f64, max: f64| PLOT_BOTTOM - v / max * (PLOT_BOTTOM - PLOT_TOP);
+    let polyline = |values: &[f64], max: f64| {
+        curve
+            .times
+            .iter()
+            .zip(values)
+            .map(|(&t, &v)| format!("{:.1},{:.1}", x_of(t), y_of(v, max)))
+            .collect::<Vec<_>>()
+            .join(" ")
+    };
+
+    let line_points = polyline(&curve.difficulty, y_max);
+    let area_points = format!(
+        "{:.1},{:.1} {} {:.1},{:.1}",
+        x_of(curve.times.first().copied().unwrap_or(0.0)),
+        PLOT_BOTTOM,
+        line_points,
+        x_of(end_time),
+        PLOT_BOTTOM
+    );
+
+    let y_ticks: Vec<Tick> = (0..=((y_max / y_step).round() as u32))
+        .map(|i| {
+            let v = i as f64 * y_step;
+            Tick {
+                x: PLOT_LEFT,
+                y: y_of(v, y_max),
+                label: format!("{}", (v * 100.0).round() / 100.0),
+            }
+        })
+        .collect();
+    let x_step = time_step(end_time);
+    let x_ticks: Vec<Tick> = (0..=((end_time / x_step).floor() as u32))
+        .map(|i| {
+            let t = i as f64 * x_step;
+            Tick {
+                x: x_of(t),
+                y: PLOT_BOTTOM,
+                label: format_time_label(t),
+            }
+        })
+        .collect();
+
+    // Ā 为不大于 1 的系数，归一化后几乎是一条直线，因此不绘制
+    let bar_sources: [(&'static str, &'static str, &[f64]); 4] = [
+        ("Jack", "rgb(255,120,120)", &curve.jack),
+        ("Density", "rgb(120,200,255)", &curve.density),
+        ("Pressing", "rgb(255,210,110)", &curve.pressing),
+        ("Release", "rgb(180,140,255)", &curve.release),
+    ];
+    let bars: Vec<BarLine> = bar_sources
+        .iter()
+        .enumerate()
+        .map(|(i, &(name, color, values))| {
+            let bar_peak = values.iter().copied().fold(0.0, f64::max);
+            let legend_x = PLOT_LEFT + i as f64 * 130.0;
+            BarLine {
+                name,
+                color,
+                points: polyline(values, if bar_peak > 0.0 { bar_peak } else { 1.0 }),
+                legend_x,
+                text_x: legend_x + 18.0,
+            }
+        })
+        .collect();
+
+    let color = format_sr_gradient(sr.unwrap_or(peak));
+    let subtitle = match sr {
+        Some(v) => format!("SR {:.2} / Peak {:.2}", v, peak),
+        None => format!("Peak {:.2}", peak),
+    };
+
+    render_template(
+        "strain_graph",
+        &json!({
+            "width": GRAPH_WIDTH,
+            "height": GRAPH_HEIGHT,
+            "plot_left": PLOT_LEFT,
+            "plot_right": PLOT_RIGHT,
+            "plot_top": PLOT_TOP,
+            "plot_bottom": PLOT_BOTTOM,
+            "label_x": PLOT_LEFT - 10.0,
+            "label_y": PLOT_BOTTOM + 20.0,
+            "legend_y": PLOT_BOTTOM + 35.0,
+            "title": title,
+            "subtitle": subtitle,
+            "color": color,
+            "line_points": line_points,
+            "area_points": area_points,
+            "x_ticks": x_ticks,
+            "y_ticks": y_ticks,
+            "bars": bars,
+        }),
+    )
+}
+
+/// 将难度曲线图保存到 `pic_path`
+pub fn save_strain_graph(
+    curve: &DifficultyCurve,
+    title: &str,
+    sr: Option<f64>,
+    pic_path: &Path,
+) -> io::Result<()> {
+    let svg_content = render_strain_graph_svg(curve, title, sr)?;
+    save_svg(&svg_content, GRAPH_WIDTH, GRAPH_HEIGHT, pic_path)
+}
+
+/// 计算谱面的难度曲线并在 `save_pic_path` 下生成 PNG，文件名为 "标题 [难度名]_strain.png"
+pub fn generate_strain_graph(data: &OsuDataLegacy, save_pic_path: &Path) -> io::Result<PathBuf> {
+    let length = data
+        .notes
+        .iter()
+        .map(|n| n.end_time.unwrap_or(n.time).max(n.time))
+        .max()
+        .unwrap_or(0) as f64;
+    let curve = calculate_curve(data, 1.0, (length / CURVE_SAMPLES).max(50.0))?;
+    let sr = calculate_from_data(data, 1.0).ok();
+
+    let title = chart_title(&data.misc);
+    let pic_path = chart_pic_path(&data.misc, save_pic_path, "_strain");
+    save_strain_graph(&curve, &title, sr, &pic_path)?;
+    Ok(pic_path)
+}
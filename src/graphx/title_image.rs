@@ -0,0 +1,40 @@
+use serde_json::json;
+use std::{io, path::Path};
+
+use super::assets::render_template;
+use super::output::{encode_svg, OutputFormat};
+use crate::osu_func::OsuMisc;
+
+/// 渲染类似信息卡风格的标题图，可以作为缺少背景图时的替代背景。
+/// 背景渐变的色相由标题决定，同一首歌生成的图片颜色一致
+pub fn render_title_image(
+    misc: &OsuMisc,
+    width: u32,
+    height: u32,
+    format: OutputFormat,
+) -> io::Result<Vec<u8>> {
+    let (title, artist) = (misc.title.as_str(), misc.artist.as_str());
+    let title_main = Some(misc.title_unicode.as_str())
+        .filter(|t| !t.is_empty())
+        .unwrap_or(title);
+    let artist_main = Some(misc.artist_unicode.as_str())
+        .filter(|a| !a.is_empty())
+        .unwrap_or(artist);
+    let hue = md5::compute(title.as_bytes())[0] as f64 / 255.0 * 360.0;
+
+    let svg_content = render_template(
+        "title_image",
+        &json!({
+            "width": width,
+            "height": height,
+            "title": title_main,
+            "title_ascii": if title_main == title { "" } else { title },
+            "artist": artist_main,
+            "artist_ascii": if artist_main == artist { "" } else { artist },
+            "creator": misc.creator,
+            "color_from": format!("hsl({:.0}, 60%, 45%)", hue),
+            "color_to": format!("hsl({:.0}, 55%, 20%)", (hue + 40.0) % 360.0),
+        }),
+    )?;
+    encode_svg(&svg_content, Path::new("."), width, height, format, 1.0)
+}
@@ -1,13 +1,22 @@
+pub mod audio;
+pub mod cache;
 pub mod graphx;
 pub mod malody_func;
 pub mod misc;
+pub mod osr;
 pub mod osu_func;
+pub mod transform;
+pub mod validate;
 
+use serde::{Deserialize, Serialize};
 use std::fmt;
 
+use crate::malody_func::{estimate_level, format_level};
+use crate::osu_func::{ChartStats, Mods, PpAttributes, PpFormula, SkillsetRatings};
+
 // Some miscellaneous stuff:
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct BeatMapInfo {
     pub title: String,
     pub title_unicode: Option<String>,
@@ -18,15 +27,49 @@ pub struct BeatMapInfo {
     pub beatmap_id: u64,
     pub beatmap_set_id: i64,
     pub column_count: u8,
+    pub od: f64,
+    pub malody_level: Option<u32>, // 从难度名中解析的 Malody 等级
+    #[serde(default)]
+    pub from_malody: bool, // 是否由 Malody 谱面（.mc / .mcz）得到
     pub min_bpm: f64,
     pub max_bpm: Option<f64>,
     pub length: u32,
     pub sr: Option<f64>,
+    pub sr_ht: Option<f64>, // Half Time (0.75x)
+    pub sr_dt: Option<f64>, // Double Time (1.5x)
+    pub ratings: Vec<(String, f64)>, // (算法名, 星级)
+    pub skillsets: Option<SkillsetRatings>, // 4K only
     pub note_count: u32,
     pub ln_count: u32,
+    pub stats: Option<ChartStats>, // 只在计算星级时统计
     pub bg_name: Option<String>, // Not used in formatted display
 }
 
+impl BeatMapInfo {
+    /// NM 下计算 pp 所需的属性，未计算星级时返回 None
+    pub fn pp_attributes(&self) -> Option<PpAttributes> {
+        self.sr.map(|sr| {
+            PpAttributes::new(sr, self.od, self.note_count + self.ln_count, Mods::default())
+        })
+    }
+
+    /// 形如 "95%: 123pp | 96%: 145pp | ..." 的 pp 表
+    pub fn pp_table_string(&self, formula: PpFormula) -> Option<String> {
+        let table = self.pp_attributes()?.pp_table(formula);
+        let items: Vec<String> = table
+            .iter()
+            .map(|(acc, pp)| format!("{}%: {:.0}pp", acc, pp))
+            .collect();
+        Some(items.join(" | "))
+    }
+
+    /// 由星级估计的 Malody 等级，未计算星级时返回 None。
+    /// 估计值只作为参考，格式化输出中只对 Malody 谱面显示
+    pub fn estimated_malody_level(&self) -> Option<f64> {
+        self.sr.map(|sr| estimate_level(sr, self.column_count as u32))
+    }
+}
+
 impl fmt::Display for BeatMapInfo {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         let title_unicode_str = self.title_unicode.as_ref().map_or("".into(), |v| v.clone());
@@ -53,13 +96,43 @@ impl fmt::Display for BeatMapInfo {
             self.length % 1000
         );
 
-        let sr_str = self.sr.map_or("N/A".into(), |v| format!("{:.4}", v));
+        let mut sr_str = self.sr.map_or("N/A".into(), |v| format!("{:.4}", v));
+        if let (Some(ht), Some(dt)) = (self.sr_ht, self.sr_dt) {
+            sr_str = format!("{} (HT: {:.4}, DT: {:.4})", sr_str, ht, dt);
+        }
+        if !self.ratings.is_empty() {
+            let ratings_str: Vec<String> = self
+                .ratings
+                .iter()
+                .map(|(name, sr)| format!("{}: {:.4}", name, sr))
+                .collect();
+            sr_str = format!("{} [{}]", sr_str, ratings_str.join(", "));
+        }
         let ln_ratio = self.ln_count as f64 / (self.ln_count + self.note_count) as f64;
 
+        let skillset_str = self
+            .skillsets
+            .map_or(String::new(), |s| format!("\nSkillsets: {}", s));
+
+        // Malody 谱面的难度名中没有等级时显示由星级估计的等级
+        let level_str = match (self.malody_level, self.estimated_malody_level()) {
+            (Some(level), _) => format!("\nMalody Level: Lv.{}", level),
+            (None, Some(level)) if self.from_malody => format!(
+                "\nMalody Level: {} (estimated)",
+                format_level(level)
+            ),
+            _ => String::new(),
+        };
+
+        let stats_str = self
+            .stats
+            .as_ref()
+            .map_or(String::new(), |s| format!("\n{}", s));
+
         write!(
             f,
-            "Title: {}\nArtist: {}\nCreator: {}\nVersion: {}\nBeatmapID: {}\nBeatmapSetID: {}\nColumns: {}\nBPM: {}\nLength: {}\nSR: {}\nLN_Ratio: {:.3}",
-            title_str, artist_str, self.creator, self.version, self.beatmap_id, self.beatmap_set_id, self.column_count, bpm_str, length_str, sr_str, ln_ratio
+            "Title: {}\nArtist: {}\nCreator: {}\nVersion: {}\nBeatmapID: {}\nBeatmapSetID: {}\nColumns: {}\nBPM: {}\nLength: {}\nSR: {}{}{}\nLN_Ratio: {:.3}{}",
+            title_str, artist_str, self.creator, self.version, self.beatmap_id, self.beatmap_set_id, self.column_count, bpm_str, length_str, sr_str, skillset_str, level_str, ln_ratio, stats_str
         )
     }
 }
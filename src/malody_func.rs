@@ -1,15 +1,20 @@
+pub mod level;
 mod mcz2osz;
 
 use std::{fs::File, io::{self, BufReader, Read}, ops::AddAssign};
 use std::ops::Add;
 
-use crate::osu_func::{OsuDataLegacy, OsuMisc, OsuTimingPoint, OsuHitObjectLegacy};
+use crate::BeatMapInfo;
+use crate::osu_func::{analyze_patterns, calculate_chart_stats, ChartStats, OsuDataLegacy, OsuMisc, OsuTimingPoint, OsuHitObjectLegacy, PatternReport, SrReport};
 
+pub use self::level::{
+    estimate_level, estimate_level_from_report, format_level, level_to_sr, parse_level, with_level,
+};
 pub use self::mcz2osz::*;
 use serde::Deserialize;
 use rayon::prelude::*;
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Meta {
     pub creator: String,
     pub background: String,
@@ -18,15 +23,18 @@ pub struct Meta {
     pub mode: u8,
     pub song: Song,
     pub mode_ext: ModeExt,
+    /// 谱面标签（原始文件中不一定存在）
+    #[serde(default)]
+    pub tags: Vec<String>,
 }
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Song {
     pub title: String,
     pub artist: String,
     pub titleorg: Option<String>,
     pub artistorg: Option<String>,
 }
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct ModeExt {
     pub column: u8,
 }
@@ -118,13 +126,13 @@ impl From<&[u32]> for Beat {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Timing {
     pub beat: Vec<u32>,
     pub bpm: f64,
 }
 impl Timing {
-    fn beat_to_float(&self) -> f64 {
+    pub fn beat_to_float(&self) -> f64 {
         // 提取数组中的元素
         let beat_0 = self.beat[0] as f64;
         let beat_1 = self.beat[1] as f64;
@@ -137,13 +145,13 @@ impl Timing {
         result
     }
 }
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Effect {
     pub beat: Vec<u32>,
     pub scroll: f64,
 }
 impl Effect {
-    fn beat_to_float(&self) -> f64 {
+    pub fn beat_to_float(&self) -> f64 {
         // 提取数组中的元素
         let beat_0 = self.beat[0] as f64;
         let beat_1 = self.beat[1] as f64;
@@ -156,7 +164,7 @@ impl Effect {
         result
     }
 }
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct Note {
     pub beat: Vec<u32>,
     pub endbeat: Option<Vec<u32>>,
@@ -195,7 +203,7 @@ impl Note {
         self.beat_to_float()
     }
 }
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct McData {
     pub meta: Meta,
     pub time: Vec<Timing>,
@@ -204,6 +212,46 @@ pub struct McData {
 }
 
 impl McData {
+    /// 音频偏移（毫秒），记录在最后一个音符中
+    pub fn audio_offset(&self) -> f64 {
+        self.note.last().and_then(|n| n.offset).unwrap_or(0) as f64
+    }
+
+    /// 拍数转换为相对音频开头的毫秒时刻
+    pub fn beat_to_time(&self, beat: f64) -> f64 {
+        let mut time = -self.audio_offset();
+        let mut prev_beat = 0.0;
+        let mut interval = self.time.first().map_or(500.0, |t| 60000.0 / t.bpm);
+        for timing in self.time.iter().skip(1) {
+            let timing_beat = timing.beat_to_float();
+            if timing_beat >= beat {
+                break;
+            }
+            time += (timing_beat - prev_beat) * interval;
+            prev_beat = timing_beat;
+            interval = 60000.0 / timing.bpm;
+        }
+        time + (beat - prev_beat) * interval
+    }
+
+    /// 毫秒时刻转换为拍数，`beat_to_time` 的逆运算
+    pub fn time_to_beat(&self, time: f64) -> f64 {
+        let mut seg_time = -self.audio_offset();
+        let mut prev_beat = 0.0;
+        let mut interval = self.time.first().map_or(500.0, |t| 60000.0 / t.bpm);
+        for timing in self.time.iter().skip(1) {
+            let timing_beat = timing.beat_to_float();
+            let timing_time = seg_time + (timing_beat - prev_beat) * interval;
+            if timing_time >= time {
+                break;
+            }
+            seg_time = timing_time;
+            prev_beat = timing_beat;
+            interval = 60000.0 / timing.bpm;
+        }
+        prev_beat + (time - seg_time) / interval
+    }
+
     pub fn from_file(file_path: &str) -> io::Result<Self> {
         // 打开文件并使用 BufReader 读取文件内容
         let file = File::open(file_path)?;
@@ -221,6 +269,44 @@ impl McData {
         Ok(mc_data)
     }
 
+    /// 分析键型，并将出现比例最高的至多 `n` 种键型追加到 meta 的标签中
+    pub fn add_pattern_tags(&mut self, n: usize) -> io::Result<PatternReport> {
+        let report = analyze_patterns(&self.to_osu_data()?)?;
+        for tag in report.top_tags(n) {
+            if !self.meta.tags.iter().any(|t| t.eq_ignore_ascii_case(tag)) {
+                self.meta.tags.push(tag.to_string());
+            }
+        }
+        Ok(report)
+    }
+
+    /// 谱面统计信息（NPS、叠键、各列音符数等）
+    pub fn chart_stats(&self) -> io::Result<ChartStats> {
+        Ok(calculate_chart_stats(&self.to_osu_data()?))
+    }
+
+    /// 谱面信息，标题与艺术家使用 Malody 的原始元数据：
+    /// `titleorg`/`artistorg`（原文）作为 Unicode 字段，`title`/`artist` 作为罗马字字段
+    pub fn to_beatmap_info(&self, b_calc_sr: bool) -> io::Result<BeatMapInfo> {
+        self.to_beatmap_info_with_report(b_calc_sr).map(|(info, _)| info)
+    }
+
+    /// 同 `to_beatmap_info`，同时返回计算星级时得到的详细结果
+    pub fn to_beatmap_info_with_report(
+        &self,
+        b_calc_sr: bool,
+    ) -> io::Result<(BeatMapInfo, Option<SrReport>)> {
+        let (mut info, report) = self.to_osu_data()?.to_beatmap_info_with_report(b_calc_sr);
+        let song = &self.meta.song;
+        info.from_malody = true;
+        info.title = song.title.clone();
+        // 没有原文时不填写 Unicode 字段，避免显示为 "Title (Title)"
+        info.title_unicode = song.titleorg.clone().filter(|t| !t.is_empty());
+        info.artist = song.artist.clone();
+        info.artist_unicode = song.artistorg.clone().filter(|a| !a.is_empty());
+        Ok((info, report))
+    }
+
     pub fn to_osu_data(&self) -> io::Result<OsuDataLegacy> {
         // 打印解析后的数据
         // println!("{:#?}", mc_data);
@@ -241,6 +327,7 @@ impl McData {
         let mut osu_data = OsuDataLegacy {
             misc: OsuMisc {
                 audio_file_name: audio.clone(),
+                audio_lead_in: 0,
                 preview_time: self.meta.preview.unwrap_or(-1),
                 title: self
                     .meta
@@ -263,6 +350,8 @@ impl McData {
                 circle_size: self.meta.mode_ext.column as u32,
                 od: 8.0,
                 background: self.meta.background.clone(),
+                breaks: Vec::new(),
+                tags: self.meta.tags.join(" "),
             },
             timings: Vec::new(),
             notes: Vec::new(),
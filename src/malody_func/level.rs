@@ -0,0 +1,166 @@
+use crate::osu_func::SrReport;
+
+/// 各键数下 (星级, Malody 等级) 的对照点，星级升序。
+/// 对照点为手工调整的估计值，没有经过实际谱面数据的校准，只作为参考
+const LEVEL_TABLE: &[(u32, &[(f64, f64)])] = &[
+    (
+        4,
+        &[
+            (1.0, 1.0),
+            (2.0, 10.0),
+            (3.0, 17.0),
+            (4.0, 23.0),
+            (5.0, 28.0),
+            (6.0, 33.0),
+            (7.0, 37.0),
+            (8.0, 41.0),
+        ],
+    ),
+    (
+        5,
+        &[
+            (1.0, 1.0),
+            (2.0, 9.0),
+            (3.0, 16.0),
+            (4.0, 22.0),
+            (5.0, 27.0),
+            (6.0, 32.0),
+            (7.0, 36.0),
+            (8.0, 40.0),
+        ],
+    ),
+    (
+        6,
+        &[
+            (1.0, 1.0),
+            (2.0, 8.0),
+            (3.0, 15.0),
+            (4.0, 21.0),
+            (5.0, 27.0),
+            (6.0, 32.0),
+            (7.0, 36.0),
+            (8.0, 40.0),
+        ],
+    ),
+    (
+        7,
+        &[
+            (1.0, 1.0),
+            (2.0, 7.0),
+            (3.0, 14.0),
+            (4.0, 20.0),
+            (5.0, 26.0),
+            (6.0, 31.0),
+            (7.0, 35.0),
+            (8.0, 39.0),
+        ],
+    ),
+    (
+        8,
+        &[
+            (1.0, 1.0),
+            (2.0, 7.0),
+            (3.0, 13.0),
+            (4.0, 19.0),
+            (5.0, 25.0),
+            (6.0, 30.0),
+            (7.0, 34.0),
+            (8.0, 38.0),
+        ],
+    ),
+];
+
+/// 键数对应的对照表，没有对应键数时取最接近的键数
+fn table_for(column_count: u32) -> &'static [(f64, f64)] {
+    LEVEL_TABLE
+        .iter()
+        .min_by_key(|(keys, _)| keys.abs_diff(column_count))
+        .map(|(_, table)| *table)
+        .unwrap_or(&[])
+}
+
+/// 在 (x, y) 对照点之间线性插值，超出范围时按两端的斜率外推
+fn interpolate(points: &[(f64, f64)], x: f64) -> f64 {
+    match points {
+        [] => x,
+        [(px, py)] => py + x - px,
+        _ => {
+            let i = points
+                .windows(2)
+                .position(|w| x <= w[1].0)
+                .unwrap_or(points.len() - 2);
+            let ((x0, y0), (x1, y1)) = (points[i], points[i + 1]);
+            y0 + (x - x0) * (y1 - y0) / (x1 - x0)
+        }
+    }
+}
+
+/// 由星级估计 Malody 等级（未取整），最低为 1
+pub fn estimate_level(sr: f64, column_count: u32) -> f64 {
+    interpolate(table_for(column_count), sr).max(1.0)
+}
+
+/// 由星级与各项指标估计 Malody 等级。
+/// 面条与叠键占比较高时按经验略微上调，调整系数同样是手工设定的估计值
+pub fn estimate_level_from_report(report: &SrReport, column_count: u32) -> f64 {
+    let base = estimate_level(report.sr, column_count);
+    let density = report.density.mean.max(1e-9);
+    let release_share = (report.release.mean / density).min(1.0);
+    let jack_share = (report.jack.mean / density).min(1.0);
+    let adjustment = 2.0 * (release_share - 0.3).max(0.0) + 1.5 * (jack_share - 0.5).max(0.0);
+    (base + adjustment).max(1.0)
+}
+
+/// 由 Malody 等级反推星级
+pub fn level_to_sr(level: f64, column_count: u32) -> f64 {
+    let swapped: Vec<(f64, f64)> = table_for(column_count)
+        .iter()
+        .map(|&(sr, lv)| (lv, sr))
+        .collect();
+    interpolate(&swapped, level).max(0.0)
+}
+
+/// 格式化为 "Lv.23"
+pub fn format_level(level: f64) -> String {
+    format!("Lv.{}", level.round().max(1.0) as u32)
+}
+
+/// 难度名中所有作为单词开头的 "lv" 的位置，"Evolv" 等单词中的 "lv" 不计入
+fn level_marks(version: &str) -> impl Iterator<Item = usize> + '_ {
+    let bytes = version.as_bytes();
+    (0..bytes.len().saturating_sub(1)).filter(move |&i| {
+        bytes[i..i + 2].eq_ignore_ascii_case(b"lv")
+            && (i == 0 || !bytes[i - 1].is_ascii_alphanumeric())
+    })
+}
+
+/// 从难度名中解析等级，例如 "4K Another Lv.23" 或 "lv 23"
+pub fn parse_level(version: &str) -> Option<u32> {
+    level_marks(version).find_map(|i| {
+        let rest = version[i + 2..].trim_start_matches(['.', ' ']);
+        let digits: String = rest.chars().take_while(|c| c.is_ascii_digit()).collect();
+        digits.parse().ok()
+    })
+}
+
+/// 将难度名中的等级替换为 `level`，没有等级时追加在末尾
+pub fn with_level(version: &str, level: f64) -> String {
+    let level_str = format_level(level);
+    for i in level_marks(version) {
+        let rest = &version[i + 2..];
+        let separator_len = rest.len() - rest.trim_start_matches(['.', ' ']).len();
+        let digits_len = rest[separator_len..]
+            .chars()
+            .take_while(|c| c.is_ascii_digit())
+            .count();
+        if digits_len > 0 {
+            let end = i + 2 + separator_len + digits_len;
+            return format!("{}{}{}", &version[..i], level_str, &version[end..]);
+        }
+    }
+    if version.trim().is_empty() {
+        level_str
+    } else {
+        format!("{} {}", version.trim_end(), level_str)
+    }
+}
@@ -9,17 +9,71 @@ use walkdir::WalkDir;
 use zip::write::SimpleFileOptions;
 use zip::{CompressionMethod, ZipArchive, ZipWriter};
 
+use crate::cache::InfoCache;
 use crate::malody_func::McData;
 use crate::misc::sanitize_filename;
-use crate::osu_func::{OsuDataLegacy, OsuHitObjectLegacy, OsuMisc, OsuTimingPoint};
+use crate::osu_func::{analyze_patterns, OsuDataLegacy, PpFormula, OsuHitObjectLegacy, OsuMisc, OsuTimingPoint};
+use crate::transform::background::{prepare_background, BackgroundOptions};
+use crate::transform::sv::{apply_sv_operations, SvOperation};
+use crate::validate::{print_diagnostics, validate_files, ValidateOptions};
 use crate::BeatMapInfo;
 
+/// mcz → osz 转换选项
+#[derive(Debug, Clone)]
+pub struct ConvertOptions {
+    /// 是否计算星级
+    pub calc_sr: bool,
+    /// 转换后依次对 .osu 时间点执行的变速处理
+    pub sv: Vec<SvOperation>,
+    /// 打包前对生成的 .osu 执行校验并输出诊断信息
+    pub validate: Option<ValidateOptions>,
+    /// 将出现比例最高的至多 n 种键型写入 .osu 的 Tags
+    pub pattern_tags: Option<usize>,
+    /// 批量转换时使用目录下的缓存，内容未变化的谱面不再重复计算星级
+    pub use_cache: bool,
+    /// 将背景图规范化为 16:9 的 JPEG；背景图缺失时生成标题图代替
+    pub background: Option<BackgroundOptions>,
+}
+
+impl Default for ConvertOptions {
+    fn default() -> Self {
+        Self {
+            calc_sr: true,
+            sv: Vec::new(),
+            validate: None,
+            pattern_tags: None,
+            use_cache: true,
+            background: None,
+        }
+    }
+}
+
+impl ConvertOptions {
+    fn with_calc_sr(b_calc_sr: bool) -> Self {
+        Self {
+            calc_sr: b_calc_sr,
+            ..Default::default()
+        }
+    }
+}
+
 /// Convert all .mcz files under given dir to .osz files.  
 /// "." or "" will set dir to the Run Directory.
 pub fn process_whole_dir_mcz(dir: &str, b_calc_sr: bool, b_print_results: bool) -> io::Result<()> {
+    process_whole_dir_mcz_with_options(dir, &ConvertOptions::with_calc_sr(b_calc_sr), b_print_results)
+}
+
+/// Same as [`process_whole_dir_mcz`], with full conversion options.
+pub fn process_whole_dir_mcz_with_options(
+    dir: &str,
+    options: &ConvertOptions,
+    b_print_results: bool,
+) -> io::Result<()> {
     let current_dir = if dir == "" { "." } else { dir }; // 当前目录
                                                          // let results_queue = Arc::new(SegQueue::<(PathBuf, Vec<BeatMapInfo>)>::new());
 
+    let cache = options.use_cache.then(|| InfoCache::open_in_dir(current_dir));
+
     // 遍历当前目录下的所有文件
     let processed: Vec<_> = WalkDir::new(current_dir)
         .into_iter()
@@ -31,7 +85,14 @@ pub fn process_whole_dir_mcz(dir: &str, b_calc_sr: bool, b_print_results: bool)
             // 检查文件扩展名是否为 .mcz
             if path.extension() == Some(std::ffi::OsStr::new("mcz")) {
                 // 将 .mcz 文件转换为 .osz 文件
-                match process_mcz_file(path, b_calc_sr) {
+                let temp_dir = match tempdir::TempDir::new("mcz_to_osz") {
+                    Ok(dir) => dir,
+                    Err(e) => {
+                        eprintln!("Error processing {}: {}", path.display(), e);
+                        return None;
+                    }
+                };
+                match process_mcz_core(path, temp_dir.path(), options, cache.as_ref()) {
                     Ok(info_tuple) => Some(info_tuple),
                     Err(e) => {
                         eprintln!("Error processing {}: {}", path.display(), e);
@@ -44,6 +105,12 @@ pub fn process_whole_dir_mcz(dir: &str, b_calc_sr: bool, b_print_results: bool)
         })
         .collect();
 
+    if let Some(cache) = &cache {
+        if let Err(e) = cache.save() {
+            eprintln!("Failed to save cache {}: {}", cache.path().display(), e);
+        }
+    }
+
     // 收集结果
     // If you really want to use SegQueue, you must manually pop out as Arc referces can't be moved
     // Even though SeqQueue provides a `into_iter()` function... But no Copy Trait...
@@ -58,6 +125,12 @@ pub fn process_whole_dir_mcz(dir: &str, b_calc_sr: bool, b_print_results: bool)
             println!("Contains {} beatmaps:", info.len());
             for beatmap in info.iter() {
                 println!("\n{beatmap}");
+                if let Some(table) = beatmap.pp_table_string(PpFormula::Current) {
+                    println!("pp: {}", table);
+                }
+                if let Some(table) = beatmap.pp_table_string(PpFormula::Legacy) {
+                    println!("pp (legacy): {}", table);
+                }
             }
             println!("{:-<80}\n", "");
         }
@@ -82,7 +155,8 @@ where
     let temp_dir_path = temp_dir.path();
 
     // 使用原有核心处理逻辑，默认计算难度
-    let (osz_path, mut beatmap_infos) = process_mcz_core(path, temp_dir_path, true)?;
+    let (osz_path, mut beatmap_infos) =
+        process_mcz_core(path, temp_dir_path, &ConvertOptions::default(), None)?;
     beatmap_infos.sort_by(|x, y| x.sr.partial_cmp(&y.sr).unwrap());
     // 执行后处理闭包
     post_process(&beatmap_infos, temp_dir_path)?;
@@ -94,12 +168,22 @@ where
 /// 输入参数：mcz文件路径，是否计算星级<br>
 /// 输出结果：osz文件路径，内部谱面信息
 pub fn process_mcz_file(path: &Path, b_calc_sr: bool) -> io::Result<(PathBuf, Vec<BeatMapInfo>)> {
+    process_mcz_file_with_options(path, &ConvertOptions::with_calc_sr(b_calc_sr))
+}
+
+/// 将mcz文件转换为osz文件<br>
+/// 输入参数：mcz文件路径，转换选项<br>
+/// 输出结果：osz文件路径，内部谱面信息
+pub fn process_mcz_file_with_options(
+    path: &Path,
+    options: &ConvertOptions,
+) -> io::Result<(PathBuf, Vec<BeatMapInfo>)> {
     // 创建解压缩后的文件夹
     let temp_dir = tempdir::TempDir::new("mcz_to_osz")?;
     let temp_dir_path = temp_dir.path();
 
     // 正经处理过程
-    process_mcz_core(path, temp_dir_path, b_calc_sr)
+    process_mcz_core(path, temp_dir_path, options, None)
 }
 
 /// Old mcz pure process with no extra stuff.  
@@ -107,7 +191,8 @@ pub fn process_mcz_file(path: &Path, b_calc_sr: bool) -> io::Result<(PathBuf, Ve
 fn process_mcz_core(
     mcz_path: &Path,
     temp_dir_path: &Path,
-    b_calc_sr: bool,
+    options: &ConvertOptions,
+    cache: Option<&InfoCache>,
 ) -> io::Result<(PathBuf, Vec<BeatMapInfo>)> {
     let beatmap_data_vec: Arc<Mutex<Vec<BeatMapInfo>>> = Arc::new(Mutex::new(Vec::new()));
     // 在process_mcz_file中添加资源收集
@@ -169,7 +254,7 @@ fn process_mcz_core(
 
             if entry_path.extension() == Some(std::ffi::OsStr::new("mc")) {
                 let (osu_file_path, osu_data) =
-                    match process_mc_file_self(entry_path, add_files_to_required) {
+                    match process_mc_file_self(entry_path, options, add_files_to_required) {
                         Ok(data) => data,
                         Err(e) => {
                             eprintln!(
@@ -181,7 +266,27 @@ fn process_mcz_core(
                         }
                     };
 
-                let beatmap_data = osu_data.to_beatmap_info(b_calc_sr);
+                // 缓存键取生成的 .osu 内容，变速与键型标签等选项的改动也会使缓存失效
+                let cache_key = cache.and_then(|_| {
+                    let options_str = if options.calc_sr { "sr" } else { "nosr" };
+                    InfoCache::key_for_file(&osu_file_path, options_str).ok()
+                });
+                // 星级的详细结果一并写入缓存
+                let compute = || Ok(osu_data.to_beatmap_info_with_report(options.calc_sr));
+                let beatmap_data = match (cache, &cache_key) {
+                    (Some(cache), Some(key)) => cache.info_with_report_or_insert_with(key, compute),
+                    _ => compute(),
+                };
+                let beatmap_data = match beatmap_data {
+                    Ok((info, _)) => BeatMapInfo {
+                        from_malody: true,
+                        ..info
+                    },
+                    Err(e) => {
+                        eprintln!("Failed to get info of {}: {}.", osu_file_path.display(), e);
+                        return;
+                    }
+                };
                 {
                     let mut beatmap_data_vec = beatmap_data_vec.lock().unwrap();
                     beatmap_data_vec.push(beatmap_data);
@@ -191,6 +296,21 @@ fn process_mcz_core(
             }
         });
 
+    if let Some(validate_options) = &options.validate {
+        let mut osu_paths: Vec<PathBuf> = required_files
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|p| p.extension() == Some(std::ffi::OsStr::new("osu")))
+            .cloned()
+            .collect();
+        osu_paths.sort();
+        match validate_files(&osu_paths, validate_options) {
+            Ok(results) => print_diagnostics(&results),
+            Err(e) => eprintln!("Failed to validate {}: {}", mcz_path.display(), e),
+        }
+    }
+
     // 创建新的 .osz ZIP 文件
     let osz_file_path = mcz_path.with_extension("osz");
     println!("Generating .osz at: {:?}", osz_file_path);
@@ -239,7 +359,11 @@ pub fn process_mc_file(path: &Path) -> io::Result<PathBuf> {
 }
 
 /// The function used in this crate
-fn process_mc_file_self<F>(mc_file_path: &Path, callback: F) -> io::Result<(PathBuf, OsuDataLegacy)>
+fn process_mc_file_self<F>(
+    mc_file_path: &Path,
+    options: &ConvertOptions,
+    callback: F,
+) -> io::Result<(PathBuf, OsuDataLegacy)>
 where
     F: Fn(&Path, &Path) -> (),
 {
@@ -261,16 +385,13 @@ where
             .and_then(|n| n.sound.as_ref())
             .unwrap_or(&String::new()),
     );
-    if let Some(parent_path) = mc_file_path.parent() {
-        let background_path = parent_path.join(&sanitized_background);
-        let audio_path = parent_path.join(&sanitized_audio);
+    let parent_path = mc_file_path.parent().unwrap_or(Path::new("."));
+    let mut background_path = parent_path.join(&sanitized_background);
+    let audio_path = parent_path.join(&sanitized_audio);
 
-        if !background_path.exists() || !audio_path.exists() {
-            println!("{:?}, {:?}", background_path, audio_path);
-            eprintln!("Warning: Some files specified in the mc file are missing.");
-        }
-
-        callback(&background_path, &audio_path); // Add them to required_files
+    if !background_path.exists() || !audio_path.exists() {
+        println!("{:?}, {:?}", background_path, audio_path);
+        eprintln!("Warning: Some files specified in the mc file are missing.");
     }
 
     mc_data.meta.background = sanitized_background;
@@ -291,7 +412,7 @@ where
     let osu_file = File::create(osu_path)?;
     let mut writer = BufWriter::new(osu_file);
 
-    let osu_data = match convert_mc_to_osu(&mc_data).unwrap() {
+    let mut osu_data = match convert_mc_to_osu(&mc_data).unwrap() {
         Some(data) => data,
         None => {
             eprintln!("Cannot get .mc data.");
@@ -301,6 +422,30 @@ where
             ));
         }
     };
+    if !options.sv.is_empty() {
+        let end_time = osu_data
+            .notes
+            .iter()
+            .map(|n| n.end_time.unwrap_or(n.time).max(n.time))
+            .max()
+            .unwrap_or(0) as f64;
+        apply_sv_operations(&mut osu_data.timings, &options.sv, end_time);
+    }
+    if let Some(n) = options.pattern_tags {
+        match analyze_patterns(&osu_data) {
+            Ok(report) => osu_data.misc.add_tags(&report.top_tags(n)),
+            Err(e) => eprintln!("Error analyzing patterns of {:?}: {}", mc_file_path, e),
+        }
+    }
+    // 背景图处理完成后再登记需要打包的文件，使压缩包中包含处理后的背景图
+    if let Some(background_options) = &options.background {
+        match prepare_background(parent_path, &mut osu_data.misc, background_options) {
+            Ok(Some(path)) => background_path = path,
+            Ok(None) => {}
+            Err(e) => eprintln!("Error preparing background of {:?}: {}", mc_file_path, e),
+        }
+    }
+    callback(&background_path, &audio_path); // Add them to required_files
     serialize_osu_data(&mut writer, &osu_data)?;
     let osu_file_path = mc_file_path.with_extension("osu");
     Ok((osu_file_path, osu_data))
@@ -345,6 +490,7 @@ fn convert_mc_to_osu(mc_data: &McData) -> io::Result<Option<OsuDataLegacy>> {
     let mut osu_data = OsuDataLegacy {
         misc: OsuMisc {
             audio_file_name: audio.clone(),
+            audio_lead_in: 0,
             preview_time: mc_data.meta.preview.unwrap_or(-1),
             title: mc_data
                 .meta
@@ -367,6 +513,8 @@ fn convert_mc_to_osu(mc_data: &McData) -> io::Result<Option<OsuDataLegacy>> {
             circle_size: mc_data.meta.mode_ext.column as u32,
             od: 8.0,
             background: mc_data.meta.background.clone(),
+            breaks: Vec::new(),
+            tags: mc_data.meta.tags.join(" "),
         },
         timings: Vec::new(),
         notes: Vec::new(),
@@ -531,8 +679,8 @@ fn serialize_osu_data(writer: &mut BufWriter<File>, osu_data: &OsuDataLegacy) ->
     write!(writer, "AudioFilename: {}\n", osu_data.misc.audio_file_name)?;
     write!(
         writer,
-        "AudioLeadIn: 0\nPreviewTime: {}\nCountdown: 0\nSampleSet: Soft\n",
-        osu_data.misc.preview_time
+        "AudioLeadIn: {}\nPreviewTime: {}\nCountdown: 0\nSampleSet: Soft\n",
+        osu_data.misc.audio_lead_in, osu_data.misc.preview_time
     )?;
     write!(writer, "StackLeniency: 0.7\nMode: 3\nLetterboxInBreaks: 0\nSpecialStyle: 0\nWidescreenStoryboard: 1\n\n")?;
 
@@ -552,8 +700,8 @@ fn serialize_osu_data(writer: &mut BufWriter<File>, osu_data: &OsuDataLegacy) ->
     write!(writer, "Version:{}\n", osu_data.misc.version)?;
     write!(
         writer,
-        "Source:\nTags:\nBeatmapID:{}\nBeatmapSetID:{}\n\n",
-        osu_data.misc.beatmap_id, osu_data.misc.beatmap_set_id
+        "Source:\nTags:{}\nBeatmapID:{}\nBeatmapSetID:{}\n\n",
+        osu_data.misc.tags, osu_data.misc.beatmap_id, osu_data.misc.beatmap_set_id
     )?;
 
     // 构建 Difficulty 部分
@@ -0,0 +1,534 @@
+use std::fmt;
+use std::fs;
+use std::io::{self, BufReader};
+use std::path::Path;
+
+use crate::osu_func::{Judgements, Mods, OsuDataLegacy};
+
+// .osr 中的 Mod 位
+const MOD_NO_FAIL: u32 = 1;
+const MOD_EASY: u32 = 1 << 1;
+const MOD_HARD_ROCK: u32 = 1 << 4;
+const MOD_DOUBLE_TIME: u32 = 1 << 6;
+const MOD_HALF_TIME: u32 = 1 << 8;
+const MOD_NIGHTCORE: u32 = 1 << 9;
+const MOD_RANDOM: u32 = 1 << 21;
+const MOD_MIRROR: u32 = 1 << 30;
+
+/// 记录随机种子的特殊帧
+const SEED_FRAME_DELTA: i64 = -12345;
+/// 面条尾判的判定区间相对头判的倍数
+const LN_RELEASE_WINDOW_SCALE: f64 = 1.5;
+
+// 判定的序号，与 `Judgements` 的字段顺序相同
+const MEH: usize = 4;
+const MISS: usize = 5;
+
+/// 一帧按键状态
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReplayFrame {
+    /// 谱面时间（毫秒）
+    pub time: i64,
+    /// 按下的列的位掩码，第 i 位为第 i 列
+    pub keys: u32,
+}
+
+/// osu!mania 回放 (.osr)
+#[derive(Debug, Clone)]
+pub struct Replay {
+    pub mode: u8,
+    pub version: i32,
+    pub beatmap_md5: String,
+    pub player_name: String,
+    pub replay_md5: String,
+    /// 回放中记录的判定（MAX 对应 .osr 中的 geki，200 对应 katu）
+    pub judgements: Judgements,
+    pub score: u32,
+    pub max_combo: u16,
+    pub perfect: bool,
+    /// 原始的 Mod 位
+    pub mods: u32,
+    /// 血条 (时间, 血量 0~1)
+    pub life_bar: Vec<(i32, f64)>,
+    /// Windows ticks
+    pub timestamp: i64,
+    pub frames: Vec<ReplayFrame>,
+    /// Random 使用的种子
+    pub seed: Option<i32>,
+    pub online_score_id: i64,
+}
+
+/// 按顺序读取 .osr 中的基本类型（小端序）
+struct OsrReader<'a> {
+    data: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> OsrReader<'a> {
+    fn read_bytes(&mut self, len: usize) -> io::Result<&'a [u8]> {
+        if self.pos + len > self.data.len() {
+            return Err(io::Error::new(
+                io::ErrorKind::UnexpectedEof,
+                "Unexpected end of replay file",
+            ));
+        }
+        let bytes = &self.data[self.pos..self.pos + len];
+        self.pos += len;
+        Ok(bytes)
+    }
+
+    fn read_array<const N: usize>(&mut self) -> io::Result<[u8; N]> {
+        let mut array = [0; N];
+        array.copy_from_slice(self.read_bytes(N)?);
+        Ok(array)
+    }
+
+    fn read_u8(&mut self) -> io::Result<u8> {
+        Ok(self.read_bytes(1)?[0])
+    }
+
+    fn read_u16(&mut self) -> io::Result<u16> {
+        Ok(u16::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_i32(&mut self) -> io::Result<i32> {
+        Ok(i32::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_i64(&mut self) -> io::Result<i64> {
+        Ok(i64::from_le_bytes(self.read_array()?))
+    }
+
+    fn read_uleb128(&mut self) -> io::Result<usize> {
+        let mut value = 0usize;
+        let mut shift = 0;
+        loop {
+            let byte = self.read_u8()?;
+            value |= ((byte & 0x7f) as usize) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+            shift += 7;
+            if shift >= usize::BITS {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidData,
+                    "Invalid ULEB128",
+                ));
+            }
+        }
+    }
+
+    /// 0x00 表示空字符串，0x0b 后接 ULEB128 长度与 UTF-8 内容
+    fn read_string(&mut self) -> io::Result<String> {
+        match self.read_u8()? {
+            0x00 => Ok(String::new()),
+            0x0b => {
+                let len = self.read_uleb128()?;
+                let bytes = self.read_bytes(len)?;
+                Ok(String::from_utf8_lossy(bytes).into_owned())
+            }
+            b => Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid string marker: 0x{:02x}", b),
+            )),
+        }
+    }
+}
+
+fn parse_life_bar(s: &str) -> Vec<(i32, f64)> {
+    s.split(',')
+        .filter_map(|item| {
+            let (time, value) = item.split_once('|')?;
+            Some((time.trim().parse().ok()?, value.trim().parse().ok()?))
+        })
+        .collect()
+}
+
+/// 解析解压后的帧数据 "w|x|y|z,..."，w 为与上一帧的时间差，mania 中 x 为按键状态
+fn parse_frames(s: &str) -> (Vec<ReplayFrame>, Option<i32>) {
+    let mut frames = Vec::new();
+    let mut seed = None;
+    let mut time = 0i64;
+    for item in s.split(',') {
+        let parts: Vec<&str> = item.split('|').collect();
+        if parts.len() < 4 {
+            continue;
+        }
+        let Ok(delta) = parts[0].trim().parse::<i64>() else {
+            continue;
+        };
+        if delta == SEED_FRAME_DELTA {
+            seed = parts[3].trim().parse().ok();
+            continue;
+        }
+        time += delta;
+        let keys = parts[1].trim().parse::<f64>().unwrap_or(0.0).max(0.0) as u32;
+        frames.push(ReplayFrame { time, keys });
+    }
+    (frames, seed)
+}
+
+impl Replay {
+    pub fn from_file(path: &Path) -> io::Result<Self> {
+        Self::from_bytes(&fs::read(path)?)
+    }
+
+    pub fn from_bytes(data: &[u8]) -> io::Result<Self> {
+        let mut reader = OsrReader { data, pos: 0 };
+        let mode = reader.read_u8()?;
+        if mode != 3 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "This program only supports mania mode!",
+            ));
+        }
+        let version = reader.read_i32()?;
+        let beatmap_md5 = reader.read_string()?;
+        let player_name = reader.read_string()?;
+        let replay_md5 = reader.read_string()?;
+        let n300 = reader.read_u16()? as u32;
+        let n100 = reader.read_u16()? as u32;
+        let n50 = reader.read_u16()? as u32;
+        let geki = reader.read_u16()? as u32;
+        let katu = reader.read_u16()? as u32;
+        let miss = reader.read_u16()? as u32;
+        let score = reader.read_i32()? as u32;
+        let max_combo = reader.read_u16()?;
+        let perfect = reader.read_u8()? != 0;
+        let mods = reader.read_i32()? as u32;
+        let life_bar = parse_life_bar(&reader.read_string()?);
+        let timestamp = reader.read_i64()?;
+
+        let compressed_len = reader.read_i32()?.max(0) as usize;
+        let compressed = reader.read_bytes(compressed_len)?;
+        let mut decompressed = Vec::new();
+        if !compressed.is_empty() {
+            lzma_rs::lzma_decompress(&mut BufReader::new(compressed), &mut decompressed)
+                .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, format!("{:?}", e)))?;
+        }
+        let (frames, seed) = parse_frames(&String::from_utf8_lossy(&decompressed));
+        // 旧版本的回放没有这一项
+        let online_score_id = reader.read_i64().unwrap_or(0);
+
+        Ok(Self {
+            mode,
+            version,
+            beatmap_md5,
+            player_name,
+            replay_md5,
+            judgements: Judgements {
+                max: geki,
+                n300,
+                n200: katu,
+                n100,
+                n50,
+                miss,
+            },
+            score,
+            max_combo,
+            perfect,
+            mods,
+            life_bar,
+            timestamp,
+            frames,
+            seed,
+            online_score_id,
+        })
+    }
+
+    /// 转换为影响判定的 Mod 组合。Random 无法复现 osu! 的列顺序，因此不会设置 `random`
+    pub fn to_mods(&self) -> Mods {
+        let rate = if self.mods & (MOD_DOUBLE_TIME | MOD_NIGHTCORE) != 0 {
+            1.5
+        } else if self.mods & MOD_HALF_TIME != 0 {
+            0.75
+        } else {
+            1.0
+        };
+        Mods {
+            rate,
+            hard_rock: self.mods & MOD_HARD_ROCK != 0,
+            easy: self.mods & MOD_EASY != 0,
+            mirror: self.mods & MOD_MIRROR != 0,
+            random: None,
+            no_fail: self.mods & MOD_NO_FAIL != 0,
+        }
+    }
+
+    /// 将按键帧与谱面对照，重新计算判定
+    pub fn analyze(&self, data: &OsuDataLegacy) -> io::Result<ReplayAnalysis> {
+        if self.mods & MOD_RANDOM != 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "Replays with Random mod cannot be analyzed",
+            ));
+        }
+        let column_count = data.misc.circle_size;
+        if column_count == 0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "Circle size is 0, meaning no columns!",
+            ));
+        }
+        let mods = self.to_mods();
+        let windows = HitWindows::new(data.misc.od, &mods);
+
+        // 每列的音符 (开始, 结束)
+        let mut notes: Vec<Vec<(f64, Option<f64>)>> = vec![Vec::new(); column_count as usize];
+        for n in &data.notes {
+            let column = (n.x_pos * column_count / 512).min(column_count - 1);
+            let end = n.end_time.filter(|&e| e > n.time).map(|e| e as f64);
+            notes[column as usize].push((n.time as f64, end));
+        }
+        // 每列的按键事件 (时间, 是否按下)
+        let column_map = mods.column_map(column_count);
+        let mut events: Vec<Vec<(f64, bool)>> = vec![Vec::new(); column_count as usize];
+        let mut prev_keys = 0u32;
+        for frame in &self.frames {
+            let changed = frame.keys ^ prev_keys;
+            for key in 0..column_count.min(32) {
+                if changed & (1 << key) != 0 {
+                    let pressed = frame.keys & (1 << key) != 0;
+                    events[column_map[key as usize] as usize].push((frame.time as f64, pressed));
+                }
+            }
+            prev_keys = frame.keys;
+        }
+
+        let mut result = ReplayAnalysis {
+            rate: mods.rate,
+            column_offsets: vec![None; column_count as usize],
+            ..Default::default()
+        };
+        for (column, (column_notes, column_events)) in notes.iter_mut().zip(&events).enumerate() {
+            column_notes.sort_by(|a, b| a.0.total_cmp(&b.0));
+            let offsets_before = result.offsets.len();
+            judge_column(column_notes, column_events, &windows, &mut result);
+            let column_offsets = &result.offsets[offsets_before..];
+            if !column_offsets.is_empty() {
+                result.column_offsets[column] =
+                    Some(column_offsets.iter().sum::<f64>() / column_offsets.len() as f64);
+            }
+        }
+
+        let offsets = &result.offsets;
+        if !offsets.is_empty() {
+            let mean = offsets.iter().sum::<f64>() / offsets.len() as f64;
+            let variance =
+                offsets.iter().map(|o| (o - mean).powi(2)).sum::<f64>() / offsets.len() as f64;
+            result.mean_offset = mean;
+            result.unstable_rate = variance.sqrt() * 10.0;
+        }
+        result.early = offsets.iter().filter(|&&o| o < 0.0).count() as u32;
+        result.late = offsets.iter().filter(|&&o| o > 0.0).count() as u32;
+        Ok(result)
+    }
+}
+
+/// osu!stable 的判定区间（以谱面时间计，毫秒）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HitWindows {
+    pub max: f64,
+    pub great: f64,
+    pub good: f64,
+    pub ok: f64,
+    pub meh: f64,
+    /// 早于此区间的按键不会被判定
+    pub miss: f64,
+}
+
+impl HitWindows {
+    pub fn new(od: f64, mods: &Mods) -> Self {
+        let factor = mods.window_factor();
+        let od = 3.0 * od;
+        Self {
+            max: 16.0 * factor,
+            great: (64.0 - od) * factor,
+            good: (97.0 - od) * factor,
+            ok: (127.0 - od) * factor,
+            meh: (151.0 - od) * factor,
+            miss: (188.0 - od) * factor,
+        }
+    }
+
+    /// 偏差对应的判定序号（MAX = 0 … 50 = 4），超出 50 的区间时返回 None
+    fn judge(&self, offset: f64, scale: f64) -> Option<usize> {
+        [self.max, self.great, self.good, self.ok, self.meh]
+            .iter()
+            .position(|w| offset.abs() <= w * scale)
+    }
+}
+
+fn record(judgements: &mut Judgements, index: usize) {
+    match index {
+        0 => judgements.max += 1,
+        1 => judgements.n300 += 1,
+        2 => judgements.n200 += 1,
+        3 => judgements.n100 += 1,
+        4 => judgements.n50 += 1,
+        _ => judgements.miss += 1,
+    }
+}
+
+/// 判定一列中的音符。面条的判定取头尾中较差的一个，与 osu!stable 一样每个面条只计一次判定
+fn judge_column(
+    notes: &[(f64, Option<f64>)],
+    events: &[(f64, bool)],
+    windows: &HitWindows,
+    result: &mut ReplayAnalysis,
+) {
+    let rate = result.rate;
+    let to_real = |offset: f64| offset / rate;
+    let mut next = 0;
+    // 正在按住的面条 (序号, 头判)
+    let mut holding: Option<(usize, usize)> = None;
+    let release = |result: &mut ReplayAnalysis, index: usize, head: usize, time: f64| {
+        let end = notes[index].1.unwrap_or(notes[index].0);
+        let offset = time - end;
+        // 过早松开计为 miss，过晚松开计为 50
+        let tail = if offset < -windows.meh * LN_RELEASE_WINDOW_SCALE {
+            MISS
+        } else {
+            windows
+                .judge(offset, LN_RELEASE_WINDOW_SCALE)
+                .unwrap_or(MEH)
+        };
+        if tail != MISS {
+            result.release_offsets.push(to_real(offset));
+        }
+        record(&mut result.release_judgements, tail);
+        record(&mut result.judgements, head.max(tail));
+    };
+
+    for &(time, pressed) in events {
+        if !pressed {
+            if let Some((index, head)) = holding.take() {
+                release(result, index, head, time);
+            }
+            continue;
+        }
+        // 已经错过的音符
+        while next < notes.len() && notes[next].0 + windows.meh < time {
+            record(&mut result.judgements, MISS);
+            next += 1;
+        }
+        if next >= notes.len() || time < notes[next].0 - windows.miss {
+            continue;
+        }
+        let offset = time - notes[next].0;
+        match windows.judge(offset, 1.0) {
+            Some(head) => {
+                result.offsets.push(to_real(offset));
+                if notes[next].1.is_some() {
+                    holding = Some((next, head));
+                } else {
+                    record(&mut result.judgements, head);
+                }
+            }
+            // 在 miss 区间内过早按下
+            None => record(&mut result.judgements, MISS),
+        }
+        next += 1;
+    }
+    // 回放结束时仍未松开的面条
+    if let Some((index, head)) = holding {
+        let end = notes[index].1.unwrap_or(notes[index].0);
+        release(
+            result,
+            index,
+            head,
+            end + windows.miss * LN_RELEASE_WINDOW_SCALE,
+        );
+    }
+    for _ in next..notes.len() {
+        record(&mut result.judgements, MISS);
+    }
+}
+
+/// 回放的判定分析结果。偏差以实际时间计（已除以倍速），正值为偏晚
+#[derive(Debug, Clone, Default)]
+pub struct ReplayAnalysis {
+    pub judgements: Judgements,
+    pub unstable_rate: f64,
+    pub mean_offset: f64,
+    /// 每列的平均偏差，没有击中任何音符的列为 None
+    pub column_offsets: Vec<Option<f64>>,
+    /// 所有击中的音符（面条为头部）的偏差
+    pub offsets: Vec<f64>,
+    pub early: u32,
+    pub late: u32,
+    /// 面条尾判
+    pub release_judgements: Judgements,
+    pub release_offsets: Vec<f64>,
+    rate: f64,
+}
+
+/// 按 `bin_size` 毫秒分组统计，返回 (分组起点, 数量)，按起点排列
+fn histogram_of(values: &[f64], bin_size: f64) -> Vec<(f64, u32)> {
+    let bin_size = if bin_size > 0.0 { bin_size } else { 1.0 };
+    let mut bins: Vec<(i64, u32)> = Vec::new();
+    for v in values {
+        let bin = (v / bin_size).floor() as i64;
+        match bins.iter_mut().find(|(b, _)| *b == bin) {
+            Some((_, count)) => *count += 1,
+            None => bins.push((bin, 1)),
+        }
+    }
+    bins.sort_by_key(|(b, _)| *b);
+    bins.into_iter()
+        .map(|(b, count)| (b as f64 * bin_size, count))
+        .collect()
+}
+
+impl ReplayAnalysis {
+    /// 击中偏差的分布，负的分组为偏早
+    pub fn histogram(&self, bin_size: f64) -> Vec<(f64, u32)> {
+        histogram_of(&self.offsets, bin_size)
+    }
+
+    /// 面条松开偏差的分布
+    pub fn release_histogram(&self, bin_size: f64) -> Vec<(f64, u32)> {
+        histogram_of(&self.release_offsets, bin_size)
+    }
+
+    /// 面条尾判的准确率（MAX 按 320 计）
+    pub fn release_accuracy(&self) -> f64 {
+        self.release_judgements.accuracy()
+    }
+}
+
+impl fmt::Display for ReplayAnalysis {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let j = &self.judgements;
+        writeln!(
+            f,
+            "MAX {} / 300 {} / 200 {} / 100 {} / 50 {} / Miss {}",
+            j.max, j.n300, j.n200, j.n100, j.n50, j.miss
+        )?;
+        writeln!(
+            f,
+            "Accuracy: {:.2}% (stable: {:.2}%)",
+            j.accuracy() * 100.0,
+            j.legacy_accuracy() * 100.0
+        )?;
+        writeln!(
+            f,
+            "UR: {:.2}, Mean: {:+.2}ms, Early: {}, Late: {}",
+            self.unstable_rate, self.mean_offset, self.early, self.late
+        )?;
+        let columns: Vec<String> = self
+            .column_offsets
+            .iter()
+            .map(|o| o.map_or("N/A".into(), |v| format!("{:+.1}", v)))
+            .collect();
+        writeln!(f, "Column offsets: {}", columns.join(", "))?;
+        if self.release_judgements.total() > 0 {
+            write!(
+                f,
+                "LN release accuracy: {:.2}%",
+                self.release_accuracy() * 100.0
+            )?;
+        }
+        Ok(())
+    }
+}
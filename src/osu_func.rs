@@ -1,11 +1,34 @@
 pub mod calc_sr;
+mod calc_strain;
+pub mod difficulty;
 mod helper_functions;
+pub mod mods;
+pub mod patterns;
+pub mod pp;
+pub mod skillsets;
+pub mod stats;
 pub mod osz_func;
 pub mod osz2mcz;
 
-pub use calc_sr::{calculate_from_data, calculate_from_file};
+pub use calc_sr::{
+    calculate_curve, calculate_detailed, calculate_detailed_with_mods, calculate_from_data,
+    calculate_from_file, calculate_with_mods, BarStats, DifficultyCurve, IncrementalSr, SrReport,
+};
+pub use difficulty::{
+    builtin_calculators, DifficultyCalculator, LazerStrainCalculator, RebirthCalculator,
+    StableStrainCalculator,
+};
+pub use mods::Mods;
+pub use patterns::{analyze_patterns, Pattern, PatternReport, PatternSection};
+pub use pp::{Judgements, PpAttributes, PpFormula};
+pub use skillsets::{calculate_skillsets, SkillsetRatings};
+pub use stats::{calculate_chart_stats, ChartStats, StreamSection};
 use core::f64;
-pub use osz_func::{parse_osz_file, parse_osz_postprocess, parse_whole_dir_osz};
+pub use osz_func::{
+    parse_chart_dir, parse_chart_file, parse_chart_postprocess_with_cache, parse_osz_file,
+    parse_osz_postprocess, parse_osz_postprocess_with_cache, parse_whole_dir_archives,
+    parse_whole_dir_osz,
+};
 use rayon::prelude::*;
 use std::fs::File;
 use std::io::{self, BufRead, BufReader, BufWriter, Write};
@@ -16,6 +39,7 @@ use crate::malody_func::{self, McData, Meta, Beat, Note};
 #[derive(Debug, Clone)]
 pub struct OsuMisc {
     pub audio_file_name: String,
+    pub audio_lead_in: i32,
     pub preview_time: i32,
     pub title: String,
     pub title_unicode: String,
@@ -28,6 +52,29 @@ pub struct OsuMisc {
     pub circle_size: u32,
     pub od: f64,
     pub background: String,
+    pub breaks: Vec<(i32, i32)>, // Break periods (start, end) in ms
+    pub tags: String,            // 以空格分隔
+}
+
+impl OsuMisc {
+    /// 追加标签，已存在的标签（不区分大小写）不会重复添加
+    pub fn add_tags<S: AsRef<str>>(&mut self, tags: &[S]) {
+        for tag in tags {
+            let tag = tag.as_ref().trim();
+            if tag.is_empty()
+                || self
+                    .tags
+                    .split_whitespace()
+                    .any(|t| t.eq_ignore_ascii_case(tag))
+            {
+                continue;
+            }
+            if !self.tags.is_empty() {
+                self.tags.push(' ');
+            }
+            self.tags.push_str(tag);
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -48,6 +95,10 @@ pub trait HitObject: Sized {
     fn get_x_pos(&self) -> u32;
     fn get_time(&self) -> Self::TimeType;
     fn get_end_time(&self) -> Option<Self::TimeType>;
+
+    fn set_x_pos(&mut self, x_pos: u32);
+    fn set_time(&mut self, time: f64);
+    fn set_end_time(&mut self, end_time: Option<f64>);
 }
 
 #[derive(Debug, Clone)]
@@ -107,6 +158,18 @@ impl HitObject for OsuHitObjectLegacy {
     fn get_end_time(&self) -> Option<Self::TimeType> {
         self.end_time
     }
+
+    fn set_x_pos(&mut self, x_pos: u32) {
+        self.x_pos = x_pos;
+    }
+
+    fn set_time(&mut self, time: f64) {
+        self.time = time.round() as u32;
+    }
+
+    fn set_end_time(&mut self, end_time: Option<f64>) {
+        self.end_time = end_time.map(|t| t.round() as u32);
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -166,6 +229,18 @@ impl HitObject for OsuHitObjectV128 {
     fn get_end_time(&self) -> Option<Self::TimeType> {
         self.end_time
     }
+
+    fn set_x_pos(&mut self, x_pos: u32) {
+        self.x_pos = x_pos;
+    }
+
+    fn set_time(&mut self, time: f64) {
+        self.time = time;
+    }
+
+    fn set_end_time(&mut self, end_time: Option<f64>) {
+        self.end_time = end_time;
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -265,6 +340,7 @@ where
 
         let mut misc = OsuMisc {
             audio_file_name: String::new(),
+            audio_lead_in: 0,
             preview_time: 0,
             title: String::new(),
             title_unicode: String::new(),
@@ -277,6 +353,8 @@ where
             circle_size: 0,
             od: 0.0,
             background: String::new(),
+            breaks: Vec::new(),
+            tags: String::new(),
         };
 
         let mut timings = Vec::new();
@@ -300,6 +378,7 @@ where
                     if let Some((key, value)) = Self::parse_key_value(&line) {
                         match key {
                             "AudioFilename" => misc.audio_file_name = value.to_string(),
+                            "AudioLeadIn" => misc.audio_lead_in = value.parse().unwrap_or(0),
                             "PreviewTime" => misc.preview_time = value.parse().unwrap_or(0),
                             "Mode" => {
                                 let v = value.parse().unwrap_or(0);
@@ -316,6 +395,7 @@ where
                             "ArtistUnicode" => misc.artist_unicode = value.to_string(),
                             "Creator" => misc.creator = value.to_string(),
                             "Version" => misc.version = value.to_string(),
+                            "Tags" => misc.tags = value.to_string(),
                             "BeatmapID" => misc.beatmap_id = value.parse().unwrap_or(0),
                             "BeatmapSetID" => misc.beatmap_set_id = value.parse().unwrap_or(-1),
                             "CircleSize" => {
@@ -334,6 +414,10 @@ where
                     let parts: Vec<&str> = line.split(',').collect();
                     if parts.len() >= 3 && parts[0] == "0" && parts[1] == "0" {
                         misc.background = parts[2].trim_matches('"').to_string();
+                    } else if parts.len() >= 3 && (parts[0] == "2" || parts[0] == "Break") {
+                        if let (Ok(start), Ok(end)) = (parts[1].parse(), parts[2].parse()) {
+                            misc.breaks.push((start, end));
+                        }
                     }
                 }
                 Section::TimingPoints => {
@@ -369,8 +453,8 @@ where
         write!(writer, "AudioFilename: {}\n", self.misc.audio_file_name)?;
         write!(
             writer,
-            "AudioLeadIn: 0\nPreviewTime: {}\nCountdown: 0\nSampleSet: Soft\n",
-            self.misc.preview_time
+            "AudioLeadIn: {}\nPreviewTime: {}\nCountdown: 0\nSampleSet: Soft\n",
+            self.misc.audio_lead_in, self.misc.preview_time
         )?;
         write!(writer, "StackLeniency: 0.7\nMode: 3\nLetterboxInBreaks: 0\nSpecialStyle: 0\nWidescreenStoryboard: 1\n\n")?;
 
@@ -390,8 +474,8 @@ where
         write!(writer, "Version:{}\n", self.misc.version)?;
         write!(
             writer,
-            "Source:\nTags:\nBeatmapID:{}\nBeatmapSetID:{}\n\n",
-            self.misc.beatmap_id, self.misc.beatmap_set_id
+            "Source:\nTags:{}\nBeatmapID:{}\nBeatmapSetID:{}\n\n",
+            self.misc.tags, self.misc.beatmap_id, self.misc.beatmap_set_id
         )?;
 
         // 构建 Difficulty 部分
@@ -408,10 +492,11 @@ where
         if !self.misc.background.is_empty() {
             write!(writer, "0,0,\"{}\",0,0\n", self.misc.background)?;
         }
-        write!(
-            writer,
-            "//Break Periods\n//Storyboard Layer 0 (Background)\n"
-        )?;
+        writeln!(writer, "//Break Periods")?;
+        for (start, end) in &self.misc.breaks {
+            writeln!(writer, "2,{},{}", start, end)?;
+        }
+        writeln!(writer, "//Storyboard Layer 0 (Background)")?;
         write!(
             writer,
             "//Storyboard Layer 1 (Fail)\n//Storyboard Layer 2 (Pass)\n"
@@ -510,9 +595,16 @@ where
     }
 
     pub fn to_beatmap_info(&self, b_calc_sr: bool) -> BeatMapInfo {
+        self.to_beatmap_info_with_report(b_calc_sr).0
+    }
+
+    /// 同 `to_beatmap_info`，同时返回计算星级时得到的详细结果，以便写入缓存
+    pub fn to_beatmap_info_with_report(&self, b_calc_sr: bool) -> (BeatMapInfo, Option<SrReport>) {
         let (min_bpm, max_bpm) = self.get_bpm_range();
 
         let length = self.get_length();
+        // 星级与统计都需要转换为 Legacy 格式，不计算星级时跳过
+        let legacy = b_calc_sr.then(|| self.clone().to_legacy());
 
         let note_count = self.notes.len() as u32;
         let ln_count = self
@@ -521,7 +613,12 @@ where
             .filter(|&n| n.get_end_time().is_some())
             .count() as u32;
 
-        BeatMapInfo {
+        // 与 RebirthCalculator 的结果一致
+        let report = legacy
+            .as_ref()
+            .and_then(|legacy| calculate_detailed(legacy, 1.0).ok());
+
+        let info = BeatMapInfo {
             title: self.misc.title.clone(),
             title_unicode: Some(self.misc.title_unicode.clone()),
             artist: self.misc.artist.clone(),
@@ -531,21 +628,67 @@ where
             beatmap_id: self.misc.beatmap_id,
             beatmap_set_id: self.misc.beatmap_set_id,
             column_count: self.misc.circle_size as u8,
+            od: self.misc.od,
+            malody_level: malody_func::parse_level(&self.misc.version),
+            from_malody: false,
             min_bpm: min_bpm,
             max_bpm: max_bpm,
             length: length,
-            sr: if b_calc_sr {
-                match calculate_from_data(&self.clone().to_legacy(), 1.0) {
-                    Ok(sr) => Some(sr.max(0.0)),
-                    Err(_) => None,
-                }
-            } else {
-                None
-            },
+            sr: report.map(|r| r.sr.max(0.0)),
+            sr_ht: None,
+            sr_dt: None,
+            ratings: Vec::new(),
+            skillsets: legacy
+                .as_ref()
+                .filter(|_| self.misc.circle_size == 4)
+                .and_then(|legacy| calculate_skillsets(legacy, &Mods::default()).ok()),
             note_count: note_count - ln_count,
             ln_count: ln_count,
+            stats: legacy.as_ref().map(calculate_chart_stats),
             bg_name: Some(self.misc.background.clone()),
-        }
+        };
+        (info, report)
+    }
+
+    /// 同时计算 NM、HT、DT 三种倍速下的星级
+    pub fn to_beatmap_info_with_rates(&self) -> BeatMapInfo {
+        let mut info = self.to_beatmap_info(false);
+        let data = self.clone().to_legacy();
+        let calc = |mods: Mods| calculate_with_mods(&data, &mods).ok().map(|sr| sr.max(0.0));
+        let (sr, (sr_ht, sr_dt)) = rayon::join(
+            || calc(Mods::default()),
+            || rayon::join(|| calc(Mods::half_time()), || calc(Mods::double_time())),
+        );
+        info.sr = sr;
+        info.sr_ht = sr_ht;
+        info.sr_dt = sr_dt;
+        info.stats = Some(calculate_chart_stats(&data));
+        info
+    }
+
+    /// 使用多个星级算法计算，结果按算法名记录在 `ratings` 中，
+    /// `sr` 取第一个算法的结果
+    pub fn to_beatmap_info_with_ratings(
+        &self,
+        calculators: &[Box<dyn DifficultyCalculator>],
+        mods: &Mods,
+    ) -> BeatMapInfo {
+        let mut info = self.to_beatmap_info(false);
+        let data = self.clone().to_legacy();
+        info.ratings = calculators
+            .par_iter()
+            .filter_map(|c| {
+                c.calculate(&data, mods)
+                    .ok()
+                    .map(|sr| (c.name().to_string(), sr))
+            })
+            .collect();
+        info.sr = calculators
+            .first()
+            .and_then(|c| info.ratings.iter().find(|(name, _)| name == c.name()))
+            .map(|(_, sr)| *sr);
+        info.stats = Some(calculate_chart_stats(&data));
+        info
     }
 }
 
@@ -597,6 +740,15 @@ impl From<OsuHitObjectLegacy> for OsuHitObjectV128 {
 }
 
 impl OsuDataLegacy {
+    /// 转换为 McData，并按星级估计的等级在难度名中写入 "Lv.xx"
+    pub fn to_mc_data_with_level(&self) -> io::Result<McData> {
+        let sr = calculate_from_data(self, 1.0)?;
+        let level = malody_func::estimate_level(sr.max(0.0), self.misc.circle_size);
+        let mut mc_data = self.to_mc_data();
+        mc_data.meta.version = malody_func::with_level(&mc_data.meta.version, level);
+        Ok(mc_data)
+    }
+
     pub fn to_mc_data(&self) -> McData {
         // 轨道数
         let column_num = self.misc.circle_size;
@@ -633,6 +785,7 @@ impl OsuDataLegacy {
             mode: 0, 
             song,
             mode_ext,
+            tags: self.misc.tags.split_whitespace().map(String::from).collect(),
         };
 
         let timings = original_timings
@@ -1,31 +1,34 @@
 use crate::osu_func::helper_functions::*;
-use crate::osu_func::OsuDataLegacy;
+use crate::osu_func::{Mods, OsuDataLegacy, OsuHitObjectLegacy};
 
+use serde::{Deserialize, Serialize};
 use std::borrow::Cow;
 use std::collections::BTreeMap;
 use std::collections::HashSet;
+use std::fmt;
 use std::io;
 
-fn preprocess(
-    osu_data: &OsuDataLegacy,
-    speed: f64,
-) -> Result<
-    (
-        f64,                       // x, a timing window param
-        u32,                       // k, num of columns
-        u32,                       // T, total time
-        Vec<(u32, u32, i32)>,      // note_seq, note sequence
-        Vec<Vec<(u32, u32, i32)>>, // note_seq_by_column, note sequence grouped by column
-        Vec<(u32, u32, u32)>,      // ln_seq, long note sequence
-        Vec<(u32, u32, u32)>,      // tail_seq, long note sequence sorted by end time (tail time)
-        Vec<Vec<(u32, u32, u32)>>, // ln_seq_by_column, long note sequence grouped by column
-    ),
-    io::Error,
-> {
-    let time_multiplier = match speed {
-        0.5..2.0 => 1.0 / speed,
-        _ => 1.0,
-    };
+/// `preprocess` 的结果：按时间排序的音符序列，时间已按倍速缩放
+pub(crate) struct Preprocessed {
+    /// 判定区间参数
+    pub x: f64,
+    /// 列数
+    pub k: u32,
+    /// 总时长
+    pub t: u32,
+    /// (列, 开始, 结束)，非面条的结束为 -1
+    pub note_seq: Vec<(u32, u32, i32)>,
+    pub note_seq_by_column: Vec<Vec<(u32, u32, i32)>>,
+    /// 面条序列 (列, 开始, 结束)
+    pub ln_seq: Vec<(u32, u32, u32)>,
+    /// 按结束时间排序的面条序列
+    pub tail_seq: Vec<(u32, u32, u32)>,
+    pub ln_seq_by_column: Vec<Vec<(u32, u32, u32)>>,
+}
+
+pub(crate) fn preprocess(osu_data: &OsuDataLegacy, mods: &Mods) -> io::Result<Preprocessed> {
+    mods.check()?;
+    let time_multiplier = 1.0 / mods.rate;
 
     if osu_data.misc.circle_size == 0 {
         return Err(io::Error::new(
@@ -34,13 +37,14 @@ fn preprocess(
         ));
     }
 
+    let column_map = mods.column_map(osu_data.misc.circle_size);
     let mut note_seq: Vec<(u32, u32, i32)> = osu_data
         .notes
         .iter()
         .map(|note| {
             let cs = osu_data.misc.circle_size;
             let k = note.x_pos * cs / 512;
-            let k = k.min(cs - 1);
+            let k = column_map[k.min(cs - 1) as usize];
             let h = (note.time as f64 * time_multiplier) as u32;
             let t = note
                 .end_time
@@ -50,7 +54,8 @@ fn preprocess(
         .collect();
 
     let x = {
-        let base = 0.3 * ((64.5 - (osu_data.misc.od * 3.0).ceil()) / 500.0).sqrt();
+        // 判定区间随 HR / EZ 缩放
+        let base = 0.3 * (mods.great_window(osu_data.misc.od).max(0.0) / 500.0).sqrt();
         base.min(0.6 * (base - 0.09) + 0.09)
     };
 
@@ -86,7 +91,7 @@ fn preprocess(
         .unwrap_or(0)
         + 1;
 
-    Ok((
+    Ok(Preprocessed {
         x,
         k,
         t,
@@ -95,7 +100,7 @@ fn preprocess(
         ln_seq,
         tail_seq,
         ln_seq_by_column,
-    ))
+    })
 }
 
 fn get_corners(total: u32, note_seq: &[(u32, u32, i32)]) -> (Vec<u32>, Vec<u32>, Vec<u32>) {
@@ -721,10 +726,35 @@ pub fn calculate_from_file(file_path: &str, speed: f64) -> io::Result<f64> {
     calculate_from_data(&data, speed)
 }
 
-pub fn calculate_from_data(data: &OsuDataLegacy, speed: f64) -> io::Result<f64> {
+/// 各项指标在全部角点上的插值结果，以及最终的难度序列
+struct StrainData {
+    corners: Vec<f64>,
+    j_bar: Vec<f64>,
+    x_bar: Vec<f64>,
+    p_bar: Vec<f64>,
+    a_bar: Vec<f64>,
+    r_bar: Vec<f64>,
+    c: Vec<f64>,
+    ks: Vec<f64>,
+    d: Vec<f64>,
+    /// 每个角点覆盖的时间长度
+    gaps: Vec<f64>,
+    /// 音符数（面条按长度折算）
+    total_notes: f64,
+}
+
+fn compute_strains(data: &OsuDataLegacy, mods: &Mods) -> io::Result<StrainData> {
     // ln_seq_by_column is not used in the calculation
-    let (x, k, t, note_seq, note_seq_by_column, ln_seq, tail_seq, _ln_seq_by_column) =
-        preprocess(data, speed)?;
+    let Preprocessed {
+        x,
+        k,
+        t,
+        note_seq,
+        note_seq_by_column,
+        ln_seq,
+        tail_seq,
+        ..
+    } = preprocess(data, mods)?;
 
     let (corners_all, corners_base, corners_a) = get_corners(t, &note_seq);
     let base_corners_f: Vec<f64> = corners_base.iter().map(|&x| x as f64).collect();
@@ -738,48 +768,75 @@ pub fn calculate_from_data(data: &OsuDataLegacy, speed: f64) -> io::Result<f64>
     let key_usage_400 = get_key_usage_400(k, t, &note_seq, &corners_base);
     let anchor = compute_anchor(k, &key_usage_400, &corners_base);
 
-    let (delta_ks, j_bar) = compute_j_bar(k, t, x, &note_seq_by_column, &base_corners_f);
-    let j_bar_interp = interp_values(&all_corners_f, &base_corners_f, &j_bar);
-
-    let x_bar = compute_x_bar(
-        k,
-        t,
-        x,
-        &note_seq_by_column,
-        &active_columns,
-        &base_corners_f,
-    );
-    let x_bar_interp = interp_values(&all_corners_f, &base_corners_f, &x_bar);
-
     let ln_rep = ln_bodies_count_sparse_representation(&tail_seq, t);
 
-    let p_bar = compute_p_bar(
-        k,
-        t,
-        x,
-        &note_seq,
-        (&ln_rep.0, &ln_rep.1, &ln_rep.2),
-        &anchor,
-        &base_corners_f,
+    // 各项指标互相独立（Ā 依赖 J̄ 的 delta_ks），并行计算
+    let ((j_bar, a_bar), (x_bar, (p_bar, (r_bar, (c_step, ks_step))))) = rayon::join(
+        || {
+            let (delta_ks, j_bar) = compute_j_bar(k, t, x, &note_seq_by_column, &base_corners_f);
+            let a_bar = compute_a_bar(
+                k,
+                t,
+                x,
+                &note_seq_by_column,
+                &active_columns,
+                &delta_ks,
+                &a_corners_f,
+                &base_corners_f,
+            );
+            (j_bar, a_bar)
+        },
+        || {
+            rayon::join(
+                || {
+                    compute_x_bar(
+                        k,
+                        t,
+                        x,
+                        &note_seq_by_column,
+                        &active_columns,
+                        &base_corners_f,
+                    )
+                },
+                || {
+                    rayon::join(
+                        || {
+                            compute_p_bar(
+                                k,
+                                t,
+                                x,
+                                &note_seq,
+                                (&ln_rep.0, &ln_rep.1, &ln_rep.2),
+                                &anchor,
+                                &base_corners_f,
+                            )
+                        },
+                        || {
+                            rayon::join(
+                                || {
+                                    compute_r_bar(
+                                        k,
+                                        t,
+                                        x,
+                                        &note_seq_by_column,
+                                        &tail_seq,
+                                        &base_corners_f,
+                                    )
+                                },
+                                || compute_c_and_ks(k, t, &note_seq, &key_usage, &base_corners_f),
+                            )
+                        },
+                    )
+                },
+            )
+        },
     );
-    let p_bar_interp = interp_values(&all_corners_f, &base_corners_f, &p_bar);
 
-    let a_bar = compute_a_bar(
-        k,
-        t,
-        x,
-        &note_seq_by_column,
-        &active_columns,
-        &delta_ks,
-        &a_corners_f,
-        &base_corners_f,
-    );
+    let j_bar_interp = interp_values(&all_corners_f, &base_corners_f, &j_bar);
+    let x_bar_interp = interp_values(&all_corners_f, &base_corners_f, &x_bar);
+    let p_bar_interp = interp_values(&all_corners_f, &base_corners_f, &p_bar);
     let a_bar_interp = interp_values(&all_corners_f, &a_corners_f, &a_bar);
-
-    let r_bar = compute_r_bar(k, t, x, &note_seq_by_column, &tail_seq, &base_corners_f);
     let r_bar_interp = interp_values(&all_corners_f, &base_corners_f, &r_bar);
-
-    let (c_step, ks_step) = compute_c_and_ks(k, t, &note_seq, &key_usage, &base_corners_f);
     let c_arr = step_interp(&all_corners_f, &base_corners_f, &c_step);
     let ks_arr = step_interp(&all_corners_f, &base_corners_f, &ks_step);
 
@@ -808,16 +865,55 @@ pub fn calculate_from_data(data: &OsuDataLegacy, speed: f64) -> io::Result<f64>
         .map(|(s, t)| 2.7 * s.powf(0.5) * t.powf(1.5) + s * 0.27)
         .collect();
 
-    let mut gaps = vec![0.0; all_corners_f.len()];
-    if all_corners_f.len() > 1 {
-        gaps[0] = (all_corners_f[1] - all_corners_f[0]) / 2.0;
-        gaps[all_corners_f.len() - 1] =
-            (all_corners_f[all_corners_f.len() - 1] - all_corners_f[all_corners_f.len() - 2]) / 2.0;
-        for i in 1..all_corners_f.len() - 1 {
-            gaps[i] = (all_corners_f[i + 1] - all_corners_f[i - 1]) / 2.0;
+    let gaps = corner_gaps(&all_corners_f);
+
+    let total_notes = total_note_weight(&note_seq, &ln_seq);
+
+    Ok(StrainData {
+        corners: all_corners_f,
+        j_bar: j_bar_interp,
+        x_bar: x_bar_interp,
+        p_bar: p_bar_interp,
+        a_bar: a_bar_interp,
+        r_bar: r_bar_interp,
+        c: c_arr,
+        ks: ks_arr,
+        d: d_all,
+        gaps,
+        total_notes,
+    })
+}
+
+/// 每个角点覆盖的时间长度
+fn corner_gaps(corners: &[f64]) -> Vec<f64> {
+    let len = corners.len();
+    let mut gaps = vec![0.0; len];
+    if len > 1 {
+        gaps[0] = (corners[1] - corners[0]) / 2.0;
+        gaps[len - 1] = (corners[len - 1] - corners[len - 2]) / 2.0;
+        for i in 1..len - 1 {
+            gaps[i] = (corners[i + 1] - corners[i - 1]) / 2.0;
         }
     }
+    gaps
+}
+
+/// 音符数（面条按长度折算），用于调整最终结果
+fn total_note_weight(note_seq: &[(u32, u32, i32)], ln_seq: &[(u32, u32, u32)]) -> f64 {
+    note_seq.len() as f64
+        + ln_seq
+            .iter()
+            .map(|&(_, h, t)| t.saturating_sub(h).min(1000) as f64 / 400.0)
+            .sum::<f64>()
+}
 
+/// 由每个角点的难度与局部音符数汇总得到星级，返回 (星级, 93%, 83%, 加权平均, 音符数系数)
+fn aggregate_sr(
+    d_all: &[f64],
+    c_arr: &[f64],
+    gaps: &[f64],
+    total_notes: f64,
+) -> (f64, f64, f64, f64, f64) {
     let effective_weights: Vec<f64> = c_arr.iter().zip(gaps.iter()).map(|(c, g)| c * g).collect();
 
     // 按照 d 值排序
@@ -858,16 +954,384 @@ pub fn calculate_from_data(data: &OsuDataLegacy, speed: f64) -> io::Result<f64>
     let mut sr =
         (0.88 * percentile_93) * 0.25 + (0.94 * percentile_83) * 0.2 + weighted_mean * 0.55;
 
-    // 调整最终结果
-    let total_notes = note_seq.len() as f64
-        + ln_seq
-            .iter()
-            .map(|&(_, h, t)| t.saturating_sub(h).min(1000) as f64 / 400.0)
-            .sum::<f64>();
-
-    sr *= total_notes / (total_notes + 60.0);
+    let note_count_factor = total_notes / (total_notes + 60.0);
+    sr *= note_count_factor;
     sr = rescale_high(sr);
     sr *= 0.975;
 
-    Ok(sr)
+    (
+        sr,
+        percentile_93,
+        percentile_83,
+        weighted_mean,
+        note_count_factor,
+    )
+}
+
+/// 某项指标的时间加权平均值与峰值
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct BarStats {
+    pub mean: f64,
+    pub peak: f64,
+}
+
+impl BarStats {
+    fn from_values(values: &[f64], gaps: &[f64]) -> Self {
+        let total: f64 = gaps.iter().sum();
+        let mean = if total > 0.0 {
+            values.iter().zip(gaps).map(|(v, g)| v * g).sum::<f64>() / total
+        } else {
+            0.0
+        };
+        let peak = values.iter().copied().fold(0.0, f64::max);
+        Self { mean, peak }
+    }
+}
+
+/// 星级计算的详细结果
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct SrReport {
+    pub sr: f64,
+    pub percentile_93: f64,
+    pub percentile_83: f64,
+    pub weighted_mean: f64,
+    /// total_notes / (total_notes + 60)，音符较少时降低星级
+    pub note_count_factor: f64,
+    /// 叠键 (J̄)
+    pub jack: BarStats,
+    /// 密度 (X̄)
+    pub density: BarStats,
+    /// 面条与按压 (P̄)
+    pub pressing: BarStats,
+    /// 左右手不对称 (Ā)
+    pub unevenness: BarStats,
+    /// 面条释放 (R̄)
+    pub release: BarStats,
+    /// 局部音符数 (C)
+    pub note_count: BarStats,
+    /// 活跃列数 (Ks)
+    pub active_keys: BarStats,
+}
+
+impl fmt::Display for SrReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let bar = |b: &BarStats| format!("{:.3} / {:.3}", b.mean, b.peak);
+        write!(
+            f,
+            "SR: {:.4}\n93%: {:.4}\n83%: {:.4}\nWeighted Mean: {:.4}\nNote Count Factor: {:.4}\n\
+            (mean / peak)\nJack: {}\nDensity: {}\nPressing: {}\nUnevenness: {}\nRelease: {}\nNote Count: {}\nActive Keys: {}",
+            self.sr,
+            self.percentile_93,
+            self.percentile_83,
+            self.weighted_mean,
+            self.note_count_factor,
+            bar(&self.jack),
+            bar(&self.density),
+            bar(&self.pressing),
+            bar(&self.unevenness),
+            bar(&self.release),
+            bar(&self.note_count),
+            bar(&self.active_keys)
+        )
+    }
+}
+
+/// 计算 `speed` 倍速下的星级，倍速必须为正数
+pub fn calculate_from_data(data: &OsuDataLegacy, speed: f64) -> io::Result<f64> {
+    calculate_with_mods(data, &Mods::with_rate(speed))
+}
+
+/// 计算指定 Mod 组合下的星级
+pub fn calculate_with_mods(data: &OsuDataLegacy, mods: &Mods) -> io::Result<f64> {
+    calculate_detailed_with_mods(data, mods).map(|report| report.sr)
+}
+
+/// 计算星级，并返回各项指标的分解结果
+pub fn calculate_detailed(data: &OsuDataLegacy, speed: f64) -> io::Result<SrReport> {
+    calculate_detailed_with_mods(data, &Mods::with_rate(speed))
+}
+
+/// 计算指定 Mod 组合下的星级，并返回各项指标的分解结果
+pub fn calculate_detailed_with_mods(data: &OsuDataLegacy, mods: &Mods) -> io::Result<SrReport> {
+    let StrainData {
+        corners: _,
+        j_bar,
+        x_bar,
+        p_bar,
+        a_bar,
+        r_bar,
+        c: c_arr,
+        ks,
+        d: d_all,
+        gaps,
+        total_notes,
+    } = compute_strains(data, mods)?;
+
+    let (sr, percentile_93, percentile_83, weighted_mean, note_count_factor) =
+        aggregate_sr(&d_all, &c_arr, &gaps, total_notes);
+
+    Ok(SrReport {
+        sr,
+        percentile_93,
+        percentile_83,
+        weighted_mean,
+        note_count_factor,
+        jack: BarStats::from_values(&j_bar, &gaps),
+        density: BarStats::from_values(&x_bar, &gaps),
+        pressing: BarStats::from_values(&p_bar, &gaps),
+        unevenness: BarStats::from_values(&a_bar, &gaps),
+        release: BarStats::from_values(&r_bar, &gaps),
+        note_count: BarStats::from_values(&c_arr, &gaps),
+        active_keys: BarStats::from_values(&ks, &gaps),
+    })
+}
+
+/// 随时间变化的难度曲线，各序列与 `times` 一一对应
+#[derive(Debug, Clone, Default)]
+pub struct DifficultyCurve {
+    /// 采样区间的中心时刻（毫秒，已按倍速缩放）
+    pub times: Vec<f64>,
+    /// 最终难度 D
+    pub difficulty: Vec<f64>,
+    pub jack: Vec<f64>,
+    pub density: Vec<f64>,
+    pub pressing: Vec<f64>,
+    pub unevenness: Vec<f64>,
+    pub release: Vec<f64>,
+}
+
+/// 计算难度曲线，`resolution` 为采样间隔（毫秒），每个采样为区间内的时间加权平均
+pub fn calculate_curve(
+    data: &OsuDataLegacy,
+    speed: f64,
+    resolution: f64,
+) -> io::Result<DifficultyCurve> {
+    if resolution.is_nan() || resolution <= 0.0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Curve resolution must be positive",
+        ));
+    }
+    let strains = compute_strains(data, &Mods::with_rate(speed))?;
+    let end = strains.corners.last().copied().unwrap_or(0.0);
+    let bucket_count = (end / resolution).floor() as usize + 1;
+
+    let bucket_of =
+        |time: f64| ((time / resolution).floor().max(0.0) as usize).min(bucket_count - 1);
+    let downsample = |values: &[f64]| {
+        let mut sums = vec![0.0; bucket_count];
+        let mut weights = vec![0.0; bucket_count];
+        for ((&time, &v), &g) in strains.corners.iter().zip(values).zip(&strains.gaps) {
+            let i = bucket_of(time);
+            sums[i] += v * g;
+            weights[i] += g;
+        }
+        sums.iter()
+            .zip(&weights)
+            .map(|(s, w)| if *w > 0.0 { s / w } else { 0.0 })
+            .collect::<Vec<f64>>()
+    };
+
+    Ok(DifficultyCurve {
+        times: (0..bucket_count)
+            .map(|i| (i as f64 + 0.5) * resolution)
+            .collect(),
+        difficulty: downsample(&strains.d),
+        jack: downsample(&strains.j_bar),
+        density: downsample(&strains.x_bar),
+        pressing: downsample(&strains.p_bar),
+        unevenness: downsample(&strains.a_bar),
+        release: downsample(&strains.r_bar),
+    })
+}
+
+/// 受修改影响的区域向两侧扩展的距离（毫秒）。
+/// 各指标的平滑窗口最大为 ±500ms，角点距离音符最远 ±1000ms
+const INCREMENTAL_MARGIN: u32 = 1000;
+
+/// 增量星级计算，用于编辑器中实时显示星级。
+/// 修改某一时间范围内的音符后，只在受影响区域附近的局部谱面上重新计算各项指标，
+/// 再替换该区域内的角点，结果与完整计算一致
+pub struct IncrementalSr {
+    data: OsuDataLegacy,
+    mods: Mods,
+    /// 以下与 `StrainData` 中的同名字段相同，时间已按倍速缩放
+    corners: Vec<f64>,
+    d: Vec<f64>,
+    c: Vec<f64>,
+    sr: f64,
+}
+
+impl IncrementalSr {
+    pub fn new(data: OsuDataLegacy, mods: Mods) -> io::Result<Self> {
+        let mut this = Self {
+            data,
+            mods,
+            corners: Vec::new(),
+            d: Vec::new(),
+            c: Vec::new(),
+            sr: 0.0,
+        };
+        this.recompute_all()?;
+        Ok(this)
+    }
+
+    pub fn sr(&self) -> f64 {
+        self.sr
+    }
+
+    pub fn data(&self) -> &OsuDataLegacy {
+        &self.data
+    }
+
+    pub fn into_data(self) -> OsuDataLegacy {
+        self.data
+    }
+
+    /// 完整地重新计算
+    pub fn recompute_all(&mut self) -> io::Result<f64> {
+        let strains = compute_strains(&self.data, &self.mods)?;
+        self.corners = strains.corners;
+        self.d = strains.d;
+        self.c = strains.c;
+        self.update_sr(strains.total_notes);
+        Ok(self.sr)
+    }
+
+    fn update_sr(&mut self, total_notes: f64) {
+        let gaps = corner_gaps(&self.corners);
+        self.sr = aggregate_sr(&self.d, &self.c, &gaps, total_notes).0;
+    }
+
+    /// 将开始时间在 [start, end) 内的音符替换为 `notes`（谱面时间），返回新的星级
+    pub fn replace_notes(
+        &mut self,
+        start: u32,
+        end: u32,
+        notes: Vec<OsuHitObjectLegacy>,
+    ) -> io::Result<f64> {
+        let time_multiplier = 1.0 / self.mods.rate;
+        let scaled = |time: u32| (time as f64 * time_multiplier) as u32;
+        let span = |n: &OsuHitObjectLegacy| {
+            let end_time = n.end_time.unwrap_or(n.time).max(n.time);
+            (scaled(n.time), scaled(end_time))
+        };
+
+        // 修改涉及的时间范围（已按倍速缩放，下同）
+        let (mut lo, mut hi) = (scaled(start), scaled(end));
+        for n in self
+            .data
+            .notes
+            .iter()
+            .filter(|n| (start..end).contains(&n.time))
+            .chain(&notes)
+        {
+            let (h, t) = span(n);
+            lo = lo.min(h);
+            hi = hi.max(t);
+        }
+        self.data.notes.retain(|n| !(start..end).contains(&n.time));
+        self.data.notes.extend(notes);
+        self.data.notes.sort_by_key(|n| n.time);
+
+        let pre = preprocess(&self.data, &self.mods)?;
+        let total_notes = total_note_weight(&pre.note_seq, &pre.ln_seq);
+        // 总时长变化时，结尾处的角点也会改变
+        let old_total = self.corners.last().copied().unwrap_or(0.0) as u32;
+        if old_total != pre.t {
+            hi = hi.max(pre.t).max(old_total);
+        }
+
+        // 同列的前一个与后一个音符（J̄、X̄、P̄），以及前后的面条尾（R̄）也受影响
+        for column in &pre.note_seq_by_column {
+            let i = column.partition_point(|n| n.1 < lo);
+            if i > 0 {
+                lo = lo.min(column[i - 1].1);
+            }
+            if let Some(&(_, h, t)) = column.iter().find(|n| n.1 > hi) {
+                hi = hi.max(h.max(t.max(0) as u32));
+            }
+        }
+        let i = pre.tail_seq.partition_point(|ln| ln.2 < lo);
+        if i > 0 {
+            lo = lo.min(pre.tail_seq[i - 1].2);
+        }
+        if let Some(ln) = pre
+            .tail_seq
+            .get(pre.tail_seq.partition_point(|ln| ln.2 <= hi))
+        {
+            hi = hi.max(ln.2);
+        }
+        let region = (
+            lo.saturating_sub(INCREMENTAL_MARGIN),
+            hi + INCREMENTAL_MARGIN,
+        );
+
+        // 局部计算的上下文：区域内的结果只依赖上下文内的音符，
+        // 以及跨越上下文边界的面条尾与每列在上下文前后的各一个音符
+        let mut context = (
+            region.0.saturating_sub(INCREMENTAL_MARGIN),
+            region.1 + INCREMENTAL_MARGIN,
+        );
+        let i = pre.tail_seq.partition_point(|ln| ln.2 < context.0);
+        if i > 0 {
+            context.0 = context.0.min(pre.tail_seq[i - 1].1);
+        }
+        if let Some(ln) = pre
+            .tail_seq
+            .get(pre.tail_seq.partition_point(|ln| ln.2 <= context.1))
+        {
+            context.1 = context.1.max(ln.2);
+        }
+
+        let column_count = self.data.misc.circle_size;
+        let column_of =
+            |n: &OsuHitObjectLegacy| (n.x_pos * column_count / 512).min(column_count - 1);
+        let mut prev: Vec<Option<usize>> = vec![None; column_count as usize];
+        let mut next: Vec<Option<usize>> = vec![None; column_count as usize];
+        let mut selected: Vec<usize> = Vec::new();
+        for (i, n) in self.data.notes.iter().enumerate() {
+            let (h, t) = span(n);
+            let column = column_of(n) as usize;
+            if t < context.0 {
+                prev[column] = Some(i);
+            } else if h > context.1 {
+                next[column].get_or_insert(i);
+            } else {
+                selected.push(i);
+            }
+        }
+        selected.extend(prev.iter().chain(&next).flatten());
+        selected.sort_unstable();
+
+        let local = OsuDataLegacy {
+            misc: self.data.misc.clone(),
+            timings: Vec::new(),
+            notes: selected
+                .iter()
+                .map(|&i| self.data.notes[i].clone())
+                .collect(),
+        };
+        let strains = compute_strains(&local, &self.mods)?;
+
+        // 替换区域内的角点
+        let (region_lo, region_hi) = (region.0 as f64, region.1 as f64);
+        let old_range = self.corners.partition_point(|&c| c < region_lo)
+            ..self.corners.partition_point(|&c| c <= region_hi);
+        let new_range = strains.corners.partition_point(|&c| c < region_lo)
+            ..strains.corners.partition_point(|&c| c <= region_hi);
+        self.corners.splice(
+            old_range.clone(),
+            strains.corners[new_range.clone()].iter().copied(),
+        );
+        self.d.splice(
+            old_range.clone(),
+            strains.d[new_range.clone()].iter().copied(),
+        );
+        self.c
+            .splice(old_range, strains.c[new_range].iter().copied());
+
+        self.update_sr(total_notes);
+        Ok(self.sr)
+    }
 }
@@ -0,0 +1,170 @@
+// osu! 官方的 mania 星级算法（基于 Strain）。
+// stable 与 lazer 的区别仅在于面条释放的加成：stable 为 0/1，lazer 按最近的释放间隔平滑过渡。
+
+use std::io;
+
+use crate::osu_func::{Mods, OsuDataLegacy};
+
+const INDIVIDUAL_DECAY_BASE: f64 = 0.125;
+const OVERALL_DECAY_BASE: f64 = 0.30;
+/// lazer 中释放间隔的阈值（毫秒）
+const RELEASE_THRESHOLD: f64 = 30.0;
+const SECTION_LENGTH: f64 = 400.0;
+const DECAY_WEIGHT: f64 = 0.9;
+const STAR_SCALING_FACTOR: f64 = 0.018;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum StrainVariant {
+    Stable,
+    Lazer,
+}
+
+fn apply_decay(value: f64, delta_time: f64, decay_base: f64) -> f64 {
+    value * decay_base.powf(delta_time / 1000.0)
+}
+
+struct StrainState {
+    variant: StrainVariant,
+    start_times: Vec<f64>,
+    end_times: Vec<f64>,
+    individual_strains: Vec<f64>,
+    individual_strain: f64,
+    overall_strain: f64,
+}
+
+impl StrainState {
+    fn new(variant: StrainVariant, column_count: usize) -> Self {
+        Self {
+            variant,
+            start_times: vec![0.0; column_count],
+            end_times: vec![0.0; column_count],
+            individual_strains: vec![0.0; column_count],
+            individual_strain: 0.0,
+            overall_strain: 1.0,
+        }
+    }
+
+    /// 处理一个音符，返回处理后的当前 strain
+    fn process(&mut self, column: usize, start_time: f64, end_time: f64, delta_time: f64) -> f64 {
+        let mut hold_factor = 1.0; // 有其他面条按住时的加成
+        let mut hold_addition = 0.0; // 当前面条需要在其他面条按住时释放的加成
+        let mut is_overlapping = false;
+        let mut closest_end_time = (end_time - start_time).abs();
+
+        for &other_end in &self.end_times {
+            let overlapping = other_end - start_time > 1.0 && end_time - other_end > 1.0;
+            match self.variant {
+                StrainVariant::Stable => {
+                    if overlapping {
+                        hold_addition = 1.0;
+                    }
+                    // 同时释放多个面条与释放一个一样简单
+                    if (end_time - other_end).abs() <= 1.0 {
+                        hold_addition = 0.0;
+                    }
+                }
+                StrainVariant::Lazer => {
+                    is_overlapping |= overlapping;
+                    closest_end_time = closest_end_time.min((end_time - other_end).abs());
+                }
+            }
+            if other_end - end_time > 1.0 {
+                hold_factor = 1.25;
+            }
+        }
+        if self.variant == StrainVariant::Lazer && is_overlapping {
+            hold_addition = 1.0 / (1.0 + (0.27 * (RELEASE_THRESHOLD - closest_end_time)).exp());
+        }
+
+        self.individual_strains[column] = apply_decay(
+            self.individual_strains[column],
+            start_time - self.start_times[column],
+            INDIVIDUAL_DECAY_BASE,
+        );
+        self.individual_strains[column] += 2.0 * hold_factor;
+
+        // 多押取各列中最大的 individual strain
+        self.individual_strain = if delta_time <= 1.0 {
+            self.individual_strain.max(self.individual_strains[column])
+        } else {
+            self.individual_strains[column]
+        };
+
+        self.overall_strain = apply_decay(self.overall_strain, delta_time, OVERALL_DECAY_BASE);
+        self.overall_strain += (1.0 + hold_addition) * hold_factor;
+
+        self.start_times[column] = start_time;
+        self.end_times[column] = end_time;
+
+        self.individual_strain + self.overall_strain
+    }
+
+    /// 新分段开始时的 strain
+    fn initial_strain(&self, time: f64, prev_start: f64) -> f64 {
+        apply_decay(self.individual_strain, time - prev_start, INDIVIDUAL_DECAY_BASE)
+            + apply_decay(self.overall_strain, time - prev_start, OVERALL_DECAY_BASE)
+    }
+}
+
+pub(crate) fn calculate_strain_sr(
+    data: &OsuDataLegacy,
+    mods: &Mods,
+    variant: StrainVariant,
+) -> io::Result<f64> {
+    mods.check()?;
+    let column_count = data.misc.circle_size;
+    if column_count == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Circle size is 0, meaning no columns!",
+        ));
+    }
+    let column_map = mods.column_map(column_count);
+
+    // (列, 开始, 结束)，时间按倍速缩放
+    let mut notes: Vec<(usize, f64, f64)> = data
+        .notes
+        .iter()
+        .map(|n| {
+            let column = (n.x_pos * column_count / 512).min(column_count - 1);
+            let start = n.time as f64 / mods.rate;
+            let end = n.end_time.map_or(start, |e| e as f64 / mods.rate);
+            (column_map[column as usize] as usize, start, end)
+        })
+        .collect();
+    notes.sort_by(|a, b| a.1.total_cmp(&b.1));
+
+    let mut state = StrainState::new(variant, column_count as usize);
+    let mut peaks: Vec<f64> = Vec::new();
+    let mut section_peak = 0.0;
+    let mut section_end = 0.0;
+
+    // 第一个音符只作为前一个音符参与计算
+    for (i, pair) in notes.windows(2).enumerate() {
+        let (prev, current) = (pair[0], pair[1]);
+        let (column, start_time, end_time) = current;
+        if i == 0 {
+            section_end = (start_time / SECTION_LENGTH).ceil() * SECTION_LENGTH;
+        }
+        while start_time > section_end {
+            peaks.push(section_peak);
+            section_peak = state.initial_strain(section_end, prev.1);
+            section_end += SECTION_LENGTH;
+        }
+        let strain = state.process(column, start_time, end_time, start_time - prev.1);
+        section_peak = f64::max(section_peak, strain);
+    }
+    if notes.len() > 1 {
+        peaks.push(section_peak);
+    }
+
+    peaks.retain(|&p| p > 0.0);
+    peaks.sort_by(|a, b| b.total_cmp(a));
+    let mut difficulty = 0.0;
+    let mut weight = 1.0;
+    for peak in peaks {
+        difficulty += peak * weight;
+        weight *= DECAY_WEIGHT;
+    }
+    Ok(difficulty * STAR_SCALING_FACTOR)
+}
@@ -0,0 +1,62 @@
+use std::io;
+
+use super::calc_strain::{calculate_strain_sr, StrainVariant};
+use crate::osu_func::{calculate_with_mods, Mods, OsuDataLegacy};
+
+/// 星级算法
+pub trait DifficultyCalculator: Sync {
+    /// 算法名称，用于 `BeatMapInfo::ratings`
+    fn name(&self) -> &str;
+    fn calculate(&self, data: &OsuDataLegacy, mods: &Mods) -> io::Result<f64>;
+}
+
+/// Star-Rating-Rebirth 算法（`calculate_from_data` 使用的算法）
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RebirthCalculator;
+
+impl DifficultyCalculator for RebirthCalculator {
+    fn name(&self) -> &str {
+        "Rebirth"
+    }
+
+    fn calculate(&self, data: &OsuDataLegacy, mods: &Mods) -> io::Result<f64> {
+        calculate_with_mods(data, mods).map(|sr| sr.max(0.0))
+    }
+}
+
+/// osu!stable 的 mania 星级
+#[derive(Debug, Clone, Copy, Default)]
+pub struct StableStrainCalculator;
+
+impl DifficultyCalculator for StableStrainCalculator {
+    fn name(&self) -> &str {
+        "osu!stable"
+    }
+
+    fn calculate(&self, data: &OsuDataLegacy, mods: &Mods) -> io::Result<f64> {
+        calculate_strain_sr(data, mods, StrainVariant::Stable)
+    }
+}
+
+/// osu!lazer 的 mania 星级
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LazerStrainCalculator;
+
+impl DifficultyCalculator for LazerStrainCalculator {
+    fn name(&self) -> &str {
+        "osu!lazer"
+    }
+
+    fn calculate(&self, data: &OsuDataLegacy, mods: &Mods) -> io::Result<f64> {
+        calculate_strain_sr(data, mods, StrainVariant::Lazer)
+    }
+}
+
+/// 所有内置算法，第一个为默认算法
+pub fn builtin_calculators() -> Vec<Box<dyn DifficultyCalculator>> {
+    vec![
+        Box::new(RebirthCalculator),
+        Box::new(StableStrainCalculator),
+        Box::new(LazerStrainCalculator),
+    ]
+}
@@ -0,0 +1,107 @@
+use std::io;
+
+use crate::transform::SeededRng;
+
+/// HR / EZ 对判定区间的缩放系数
+const WINDOW_MOD_FACTOR: f64 = 1.4;
+
+/// 影响星级计算的 Mod 组合
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Mods {
+    /// 倍速，DT/NC 为 1.5，HT/DC 为 0.75
+    pub rate: f64,
+    /// 判定区间缩小为 1/1.4
+    pub hard_rock: bool,
+    /// 判定区间放大为 1.4 倍
+    pub easy: bool,
+    /// 左右镜像
+    pub mirror: bool,
+    /// 按种子随机打乱列
+    pub random: Option<u64>,
+    /// 不影响星级，只影响 pp
+    pub no_fail: bool,
+}
+
+impl Default for Mods {
+    fn default() -> Self {
+        Self {
+            rate: 1.0,
+            hard_rock: false,
+            easy: false,
+            mirror: false,
+            random: None,
+            no_fail: false,
+        }
+    }
+}
+
+impl Mods {
+    pub fn with_rate(rate: f64) -> Self {
+        Self {
+            rate,
+            ..Default::default()
+        }
+    }
+
+    /// Half Time / Daycore
+    pub fn half_time() -> Self {
+        Self::with_rate(0.75)
+    }
+
+    /// Double Time / Nightcore
+    pub fn double_time() -> Self {
+        Self::with_rate(1.5)
+    }
+
+    pub fn check(&self) -> io::Result<()> {
+        if !self.rate.is_finite() || self.rate <= 0.0 {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("Invalid rate: {}", self.rate),
+            ));
+        }
+        if self.hard_rock && self.easy {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "HR and EZ cannot be used together",
+            ));
+        }
+        Ok(())
+    }
+
+    /// HR / EZ 下判定区间相对 NM 的缩放
+    pub fn window_factor(&self) -> f64 {
+        if self.hard_rock {
+            1.0 / WINDOW_MOD_FACTOR
+        } else if self.easy {
+            WINDOW_MOD_FACTOR
+        } else {
+            1.0
+        }
+    }
+
+    /// 实际时间下的 300 (Great) 判定区间（毫秒），只随 HR / EZ 缩放。
+    /// osu!mania 在 DT / HT 下会抵消倍速的影响，实际区间与 NM 相同。
+    pub fn great_window(&self, od: f64) -> f64 {
+        (64.5 - (od * 3.0).ceil()) * self.window_factor()
+    }
+
+    /// 原列号到新列号的映射
+    pub fn column_map(&self, column_count: u32) -> Vec<u32> {
+        let mut map: Vec<u32> = (0..column_count).collect();
+        if let Some(seed) = self.random {
+            // Fisher-Yates
+            let mut rng = SeededRng::new(seed);
+            for i in (1..map.len()).rev() {
+                let j = (rng.next_u64() % (i as u64 + 1)) as usize;
+                map.swap(i, j);
+            }
+        }
+        if self.mirror {
+            for c in map.iter_mut() {
+                *c = column_count - 1 - *c;
+            }
+        }
+        map
+    }
+}
@@ -1,20 +1,32 @@
+use crate::cache::InfoCache;
+use crate::malody_func::McData;
 use crate::osu_func::OsuDataV128;
 use crate::BeatMapInfo;
 
 use rayon::prelude::*;
 use std::env;
-use std::fs::File;
+use std::fs::{self, File};
 use std::io;
-use std::path::Path;
+use std::path::{Path, PathBuf};
 use std::str;
 use std::sync::{Arc, Mutex};
 use walkdir::WalkDir;
 use zip::ZipArchive;
 
-use crate::graphx::generate_osz_info;
+use crate::graphx::generate_chart_info_with_cache;
 
 pub fn parse_whole_dir_osz(dir: &str) -> io::Result<Vec<String>> {
-    let current_dir = if dir == "" { "." } else { dir };
+    parse_whole_dir_core(dir, &["osz"])
+}
+
+/// 为目录下所有 .osz 与 .mcz 文件生成信息卡，返回生成的图片位置
+pub fn parse_whole_dir_archives(dir: &str) -> io::Result<Vec<String>> {
+    parse_whole_dir_core(dir, &["osz", "mcz"])
+}
+
+fn parse_whole_dir_core(dir: &str, extensions: &[&str]) -> io::Result<Vec<String>> {
+    let current_dir = if dir.is_empty() { "." } else { dir };
+    let cache = InfoCache::open_in_dir(current_dir);
     let processed: Vec<String> = WalkDir::new(current_dir)
         .into_iter()
         .par_bridge()
@@ -22,9 +34,10 @@ pub fn parse_whole_dir_osz(dir: &str) -> io::Result<Vec<String>> {
             let entry = entry.ok()?;
             let path = entry.path();
 
-            if path.extension() == Some(std::ffi::OsStr::new("osz")) {
+            let ext = path.extension()?.to_str()?;
+            if extensions.iter().any(|e| ext.eq_ignore_ascii_case(e)) {
                 println!("{:?}", path);
-                generate_osz_info(path).ok()
+                generate_chart_info_with_cache(path, Some(&cache)).ok()
             } else {
                 None
             }
@@ -38,20 +51,76 @@ pub fn parse_whole_dir_osz(dir: &str) -> io::Result<Vec<String>> {
                 .into_owned()
         })
         .collect();
+    if let Err(e) = cache.save() {
+        eprintln!("Failed to save cache {}: {}", cache.path().display(), e);
+    }
     Ok(processed)
 }
 
 // Calc SR on default
-pub fn parse_osz_postprocess<F>(osz_path: &Path, mut post_process: F) -> io::Result<()>
+pub fn parse_osz_postprocess<F>(osz_path: &Path, post_process: F) -> io::Result<()>
 where
     F: FnMut(&[BeatMapInfo], &Path) -> io::Result<()>,
 {
-    let temp_dir = tempdir::TempDir::new("parse_osz")?;
-    let temp_dir_path = temp_dir.path();
+    parse_osz_postprocess_with_cache(osz_path, None, post_process)
+}
 
-    let mut osu_info_vec = parse_osz_core(osz_path, temp_dir_path, true)?;
-    osu_info_vec.sort_by(|x, y| x.sr.partial_cmp(&y.sr).unwrap());
-    post_process(&osu_info_vec, temp_dir_path)?;
+/// 同 [`parse_osz_postprocess`]，命中缓存的谱面不再重新计算星级
+pub fn parse_osz_postprocess_with_cache<F>(
+    osz_path: &Path,
+    cache: Option<&InfoCache>,
+    post_process: F,
+) -> io::Result<()>
+where
+    F: FnMut(&[BeatMapInfo], &Path) -> io::Result<()>,
+{
+    parse_chart_postprocess_with_cache(osz_path, cache, post_process)
+}
+
+/// 解析谱面并执行后处理函数（默认计算星级），支持：<br>
+/// .osz 与 .mcz 压缩包、单个 .osu 或 .mc 文件，以及解压后的歌曲目录<br>
+/// 后处理函数参数：按星级排序的谱面信息，背景图所在的目录
+pub fn parse_chart_postprocess_with_cache<F>(
+    path: &Path,
+    cache: Option<&InfoCache>,
+    mut post_process: F,
+) -> io::Result<()>
+where
+    F: FnMut(&[BeatMapInfo], &Path) -> io::Result<()>,
+{
+    let temp_dir = tempdir::TempDir::new("parse_chart")?;
+    let (mut info_vec, resources_dir) = if path.is_dir() {
+        (parse_chart_dir(path, true, cache)?, path.to_path_buf())
+    } else {
+        let ext = path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| e.to_ascii_lowercase());
+        match ext.as_deref() {
+            Some("osz") | Some("mcz") => (
+                parse_osz_core(path, temp_dir.path(), true, cache)?,
+                temp_dir.path().to_path_buf(),
+            ),
+            Some("osu") | Some("mc") => (
+                vec![parse_chart_file(path, true, cache)?],
+                path.parent().map_or(PathBuf::from("."), Path::to_path_buf),
+            ),
+            _ => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!("Unsupported chart file: {}", path.display()),
+                ))
+            }
+        }
+    };
+    if info_vec.is_empty() {
+        return Err(io::Error::new(
+            io::ErrorKind::NotFound,
+            format!("No chart found in {}", path.display()),
+        ));
+    }
+    info_vec.sort_by(|x, y| x.sr.partial_cmp(&y.sr).unwrap());
+    post_process(&info_vec, &resources_dir)?;
     Ok(())
 }
 
@@ -59,76 +128,153 @@ pub fn parse_osz_file(osz_path: &Path, b_calc_sr: bool) -> io::Result<Vec<BeatMa
     let temp_dir = tempdir::TempDir::new("parse_osz")?;
     let temp_dir_path = temp_dir.path();
 
-    parse_osz_core(osz_path, temp_dir_path, b_calc_sr)
+    parse_osz_core(osz_path, temp_dir_path, b_calc_sr, None)
 }
 
+/// 解压 .osz（或 .mcz）并解析其中的谱面，保留压缩包内的目录层级
 fn parse_osz_core(
     osz_path: &Path,
     temp_dir_path: &Path,
     b_calc_sr: bool,
+    cache: Option<&InfoCache>,
+) -> io::Result<Vec<BeatMapInfo>> {
+    extract_archive(osz_path, temp_dir_path)?;
+    parse_chart_dir(temp_dir_path, b_calc_sr, cache)
+}
+
+/// 解析目录（包括子目录）中所有的 .osu 与 .mc 谱面。
+/// 子目录中谱面的背景图名会加上相对于 `dir` 的路径
+pub fn parse_chart_dir(
+    dir: &Path,
+    b_calc_sr: bool,
+    cache: Option<&InfoCache>,
 ) -> io::Result<Vec<BeatMapInfo>> {
     let beatmap_data_vec: Arc<Mutex<Vec<BeatMapInfo>>> = Arc::new(Mutex::new(Vec::new()));
 
-    let file = File::open(osz_path)?;
+    WalkDir::new(dir)
+        .into_iter()
+        .par_bridge()
+        .for_each(|entry| {
+            let entry = match entry {
+                Ok(e) => e,
+                Err(_) => return,
+            };
+            let entry_path = entry.path();
+            let is_chart = entry_path
+                .extension()
+                .is_some_and(|e| e.eq_ignore_ascii_case("osu") || e.eq_ignore_ascii_case("mc"));
+            if !is_chart {
+                return;
+            }
+            let mut beatmap_data = match parse_chart_file(entry_path, b_calc_sr, cache) {
+                Ok(info) => info,
+                Err(e) => {
+                    eprintln!("Cannot get chart data {}: {e}", entry_path.display());
+                    return;
+                }
+            };
+            let sub_dir = entry_path
+                .parent()
+                .and_then(|p| p.strip_prefix(dir).ok())
+                .filter(|p| !p.as_os_str().is_empty());
+            if let (Some(sub_dir), Some(bg_name)) = (sub_dir, &beatmap_data.bg_name) {
+                beatmap_data.bg_name = Some(sub_dir.join(bg_name).to_string_lossy().into_owned());
+            }
+            beatmap_data_vec.lock().unwrap().push(beatmap_data);
+        });
+
+    Ok(Arc::try_unwrap(beatmap_data_vec)
+        .unwrap()
+        .into_inner()
+        .unwrap())
+}
+
+/// 解析单个 .osu 或 .mc 谱面，.mc 谱面保留 Malody 的原始元数据
+pub fn parse_chart_file(
+    path: &Path,
+    b_calc_sr: bool,
+    cache: Option<&InfoCache>,
+) -> io::Result<BeatMapInfo> {
+    let path_str = path.to_str().ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid path: {}", path.display()),
+        )
+    })?;
+
+    let cache_key = cache.and_then(|_| {
+        let options_str = if b_calc_sr { "sr" } else { "nosr" };
+        InfoCache::key_for_file(path, options_str).ok()
+    });
+    let compute = || {
+        if path
+            .extension()
+            .is_some_and(|e| e.eq_ignore_ascii_case("mc"))
+        {
+            McData::from_file(path_str)?.to_beatmap_info_with_report(b_calc_sr)
+        } else {
+            Ok(OsuDataV128::from_file(path_str)?.to_beatmap_info_with_report(b_calc_sr))
+        }
+    };
+    let (beatmap_data, _) = match (cache, &cache_key) {
+        (Some(cache), Some(key)) => cache.info_with_report_or_insert_with(key, compute)?,
+        _ => compute()?,
+    };
+    Ok(beatmap_data)
+}
+
+/// 将压缩包内的所有文件按原有的目录层级解压到指定目录。
+/// 条目名中含有 ".." 或绝对路径时返回错误，避免写到目录之外
+pub(crate) fn extract_archive(archive_path: &Path, dir: &Path) -> io::Result<()> {
+    let file = File::open(archive_path)?;
     let mut zip_archive = ZipArchive::new(file)?;
 
     for i in 0..zip_archive.len() {
         let mut file = zip_archive.by_index(i)?;
 
-        // 纯文件名，不含路径
-        let file_name_bytes = file.name_raw();
-        let translated_file_name = match str::from_utf8(file_name_bytes) {
+        let translated_file_name = match str::from_utf8(file.name_raw()) {
             Ok(file_name) => file_name.to_string(),
             Err(e) => {
                 eprintln!("Failed to decode file name as UTF-8: {}", e);
-                "invalid_utf8_name".to_string()
+                format!("invalid_utf8_name_{}", i)
             }
         };
-        let pure_file_name = Path::new(&translated_file_name)
-            .file_name()
-            .and_then(|n| n.to_str())
-            .unwrap();
+        let relative_path = archive_entry_path(&translated_file_name).ok_or_else(|| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid file name in archive: {}", translated_file_name),
+            )
+        })?;
+        let target_path = dir.join(relative_path);
 
-        let target_path = temp_dir_path.join(pure_file_name);
-
-        // 将文件解压到临时目录中
-        if file.is_file() {
+        if file.is_dir() {
+            fs::create_dir_all(&target_path)?;
+        } else if file.is_file() {
+            if let Some(parent) = target_path.parent() {
+                fs::create_dir_all(parent)?;
+            }
             let mut output = File::create(&target_path)?;
             io::copy(&mut file, &mut output)?;
         }
     }
+    Ok(())
+}
 
-    WalkDir::new(temp_dir_path)
-        .into_iter()
-        .par_bridge()
-        .for_each(|entry| {
-            let entry = entry.unwrap();
-            let entry_path = entry.path();
-            if entry_path.extension() == Some(std::ffi::OsStr::new("osu")) {
-                let osu_path_str = match entry_path.to_str() {
-                    Some(s) => s,
-                    None => {
-                        return;
-                    }
-                };
-
-                let osu_data = match OsuDataV128::from_file(osu_path_str) {
-                    Ok(d) => d,
-                    Err(e) => {
-                        eprintln!("Cannot get osu data: {e}");
-                        return;
-                    }
-                };
-                let beatmap_data = osu_data.to_beatmap_info(b_calc_sr);
-                {
-                    let mut beatmap_data_vec = beatmap_data_vec.lock().unwrap();
-                    beatmap_data_vec.push(beatmap_data);
-                }
-            }
-        });
-
-    Ok(Arc::try_unwrap(beatmap_data_vec)
-        .unwrap()
-        .into_inner()
-        .unwrap())
+/// 压缩包条目名对应的相对路径，兼容以 "\\" 分隔的条目名
+fn archive_entry_path(name: &str) -> Option<PathBuf> {
+    let mut path = PathBuf::new();
+    for part in name.split(['/', '\\']) {
+        match part {
+            "" | "." => {}
+            ".." => return None,
+            // 盘符等在 Windows 上会被解释为绝对路径
+            part if part.contains(':') => return None,
+            part => path.push(part),
+        }
+    }
+    if path.as_os_str().is_empty() {
+        None
+    } else {
+        Some(path)
+    }
 }
@@ -0,0 +1,413 @@
+use std::cmp::Ordering;
+use std::fmt;
+use std::io;
+
+use super::calc_sr::{preprocess, Preprocessed};
+use crate::osu_func::{Mods, OsuDataLegacy, OsuTimingPoint};
+
+/// 分段长度（毫秒）
+const WINDOW_LENGTH: f64 = 1000.0;
+/// 分段内至少有这么多行才判断键型
+const MIN_ROWS: usize = 4;
+/// 连打类键型（Stream / Jumpstream / Handstream / 交互 / 楼梯）要求的最少行数
+const MIN_STREAM_ROWS: usize = 6;
+/// 低于这一覆盖率的键型不写入标签
+const MIN_TAG_COVERAGE: f64 = 10.0;
+
+/// 键型
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub enum Pattern {
+    Stream,
+    Jumpstream,
+    Handstream,
+    Chordjack,
+    Jacks,
+    Trills,
+    Rolls,
+    /// 面条尾判密集
+    LnRelease,
+    /// 反键（面条几乎铺满所有列，只留出短暂的间隙）
+    Inverse,
+    /// 变速特效
+    SvGimmick,
+}
+
+impl Pattern {
+    pub const ALL: [Pattern; 10] = [
+        Pattern::Stream,
+        Pattern::Jumpstream,
+        Pattern::Handstream,
+        Pattern::Chordjack,
+        Pattern::Jacks,
+        Pattern::Trills,
+        Pattern::Rolls,
+        Pattern::LnRelease,
+        Pattern::Inverse,
+        Pattern::SvGimmick,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Pattern::Stream => "Stream",
+            Pattern::Jumpstream => "Jumpstream",
+            Pattern::Handstream => "Handstream",
+            Pattern::Chordjack => "Chordjack",
+            Pattern::Jacks => "Jacks",
+            Pattern::Trills => "Trills",
+            Pattern::Rolls => "Rolls",
+            Pattern::LnRelease => "LN Release",
+            Pattern::Inverse => "Inverse",
+            Pattern::SvGimmick => "SV Gimmick",
+        }
+    }
+
+    /// 写入 osu! Tags / Malody 标签时使用的名称（不含空格）
+    pub fn tag(&self) -> &'static str {
+        match self {
+            Pattern::Stream => "stream",
+            Pattern::Jumpstream => "jumpstream",
+            Pattern::Handstream => "handstream",
+            Pattern::Chordjack => "chordjack",
+            Pattern::Jacks => "jacks",
+            Pattern::Trills => "trills",
+            Pattern::Rolls => "rolls",
+            Pattern::LnRelease => "ln-release",
+            Pattern::Inverse => "inverse",
+            Pattern::SvGimmick => "sv-gimmick",
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+/// 连续出现某一键型的时间段（毫秒）
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PatternSection {
+    pub pattern: Pattern,
+    pub start: f64,
+    pub end: f64,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct PatternReport {
+    /// 各键型的覆盖率（占有音符的分段的百分比），按覆盖率从高到低排列，不含 0
+    pub coverage: Vec<(Pattern, f64)>,
+    /// 按开始时间排列的键型时间段
+    pub sections: Vec<PatternSection>,
+}
+
+impl PatternReport {
+    pub fn coverage_of(&self, pattern: Pattern) -> f64 {
+        self.coverage
+            .iter()
+            .find(|(p, _)| *p == pattern)
+            .map_or(0.0, |(_, c)| *c)
+    }
+
+    pub fn sections_of(&self, pattern: Pattern) -> impl Iterator<Item = &PatternSection> {
+        self.sections.iter().filter(move |s| s.pattern == pattern)
+    }
+
+    /// 覆盖率最高的至多 `n` 种键型的标签，覆盖率过低的键型不计入
+    pub fn top_tags(&self, n: usize) -> Vec<&'static str> {
+        self.coverage
+            .iter()
+            .filter(|(_, c)| *c >= MIN_TAG_COVERAGE)
+            .take(n)
+            .map(|(p, _)| p.tag())
+            .collect()
+    }
+}
+
+fn format_time(ms: f64) -> String {
+    let secs = (ms / 1000.0).round() as u32;
+    format!("{}:{:02}", secs / 60, secs % 60)
+}
+
+impl fmt::Display for PatternReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (pattern, coverage) in &self.coverage {
+            let ranges: Vec<String> = self
+                .sections_of(*pattern)
+                .map(|s| format!("{}-{}", format_time(s.start), format_time(s.end)))
+                .collect();
+            writeln!(
+                f,
+                "{:<12}{:>6.1}%  {}",
+                pattern.name(),
+                coverage,
+                ranges.join(", ")
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// 同一时刻的音符组成一行，记录为 (时间, 列的位掩码)
+fn build_rows(note_seq: &[(u32, u32, i32)]) -> Vec<(u32, u32)> {
+    let mut rows: Vec<(u32, u32)> = Vec::new();
+    for &(k, h, _) in note_seq {
+        match rows.last_mut() {
+            Some((time, mask)) if *time == h => *mask |= 1 << k,
+            _ => rows.push((h, 1 << k)),
+        }
+    }
+    rows
+}
+
+/// 根据分段内的行判断按键类键型
+fn classify_rows(rows: &[(u32, u32)], prev_mask: Option<u32>, k: u32, labels: &mut Vec<Pattern>) {
+    if rows.len() < MIN_ROWS {
+        return;
+    }
+    let masks: Vec<u32> = rows.iter().map(|r| r.1).collect();
+    let notes: u32 = masks.iter().map(|m| m.count_ones()).sum();
+    let total = notes as f64;
+
+    // 按多押大小统计音符占比
+    let ratio_of = |pred: fn(u32) -> bool| {
+        masks
+            .iter()
+            .filter(|m| pred(m.count_ones()))
+            .map(|m| m.count_ones())
+            .sum::<u32>() as f64
+            / total
+    };
+    let single = ratio_of(|c| c == 1);
+    let jump = ratio_of(|c| c == 2);
+    let hand = ratio_of(|c| c >= 3);
+
+    // 与前一行共用列的音符（叠键）占比
+    let mut jack_notes = 0;
+    let mut prev = prev_mask;
+    for &mask in &masks {
+        if let Some(p) = prev {
+            jack_notes += (mask & p).count_ones();
+        }
+        prev = Some(mask);
+    }
+    let jack_ratio = jack_notes as f64 / total;
+
+    if jack_ratio >= 0.3 && jump + hand >= 0.5 {
+        labels.push(Pattern::Chordjack);
+    } else if jack_ratio >= 0.4 {
+        labels.push(Pattern::Jacks);
+    } else if masks.len() >= MIN_STREAM_ROWS {
+        if hand >= 0.25 {
+            labels.push(Pattern::Handstream);
+        } else if jump >= 0.25 {
+            labels.push(Pattern::Jumpstream);
+        } else if single >= 0.6 {
+            labels.push(Pattern::Stream);
+        }
+    }
+
+    if masks.len() < MIN_STREAM_ROWS {
+        return;
+    }
+    let steps = (masks.len() - 2) as f64;
+
+    // 交互：两组互不相交的列交替出现
+    let trill_rows = masks
+        .windows(3)
+        .filter(|w| w[2] == w[0] && w[2] != w[1] && w[2] & w[1] == 0)
+        .count();
+    if trill_rows as f64 / steps >= 0.6 {
+        labels.push(Pattern::Trills);
+    }
+
+    // 楼梯：单键沿同一方向移动到相邻列（允许从一侧绕回另一侧）
+    if k >= 3 {
+        let step_of = |a: u32, b: u32| {
+            let s = (b.trailing_zeros() as i32 - a.trailing_zeros() as i32).rem_euclid(k as i32);
+            (s == 1 || s == k as i32 - 1).then_some(s)
+        };
+        let roll_rows = masks
+            .windows(3)
+            .filter(|w| w.iter().all(|m| m.count_ones() == 1))
+            .filter(|w| {
+                let s1 = step_of(w[0], w[1]);
+                s1.is_some() && s1 == step_of(w[1], w[2])
+            })
+            .count();
+        if roll_rows as f64 / steps >= 0.6 {
+            labels.push(Pattern::Rolls);
+        }
+    }
+}
+
+/// 根据面条判断尾判密集与反键
+fn classify_lns(pre: &Preprocessed, start: u32, end: u32, notes: usize, labels: &mut Vec<Pattern>) {
+    let tails = pre
+        .tail_seq
+        .iter()
+        .filter(|&&(_, _, t)| t >= start && t < end)
+        .count();
+    if tails >= MIN_ROWS && tails as f64 >= 0.5 * notes.max(1) as f64 {
+        labels.push(Pattern::LnRelease);
+    }
+
+    // 面条在分段内覆盖的时长占全部列的比例
+    let covered: u32 = pre
+        .ln_seq_by_column
+        .iter()
+        .flatten()
+        .map(|&(_, h, t)| t.min(end).saturating_sub(h.max(start)))
+        .sum();
+    let coverage = covered as f64 / (pre.k * (end - start)).max(1) as f64;
+    if coverage >= 0.7 && tails >= 2 {
+        labels.push(Pattern::Inverse);
+    }
+}
+
+/// 标记变速特效的分段：分段内多次变速，或出现极端的 SV
+fn sv_gimmick_windows(timings: &[OsuTimingPoint], window_count: usize) -> Vec<bool> {
+    let mut gimmick = vec![false; window_count];
+    let window_of = |time: f64| ((time.max(0.0) / WINDOW_LENGTH) as usize).min(window_count - 1);
+
+    let mut changes = vec![0u32; window_count];
+    let mut current_sv = 1.0;
+    let mut last_window = 0;
+    // .osu 中的时间点不保证按时间排列
+    let mut sorted: Vec<&OsuTimingPoint> = timings.iter().collect();
+    sorted.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(Ordering::Equal));
+    for tp in sorted {
+        let sv = if tp.is_timing {
+            1.0
+        } else if tp.val < 0.0 {
+            -100.0 / tp.val
+        } else {
+            continue;
+        };
+        let window = window_of(tp.time);
+        // 极端 SV 持续期间的分段均标记
+        if !(0.5..=2.0).contains(&current_sv) {
+            for g in &mut gimmick[last_window..=window] {
+                *g = true;
+            }
+        }
+        if (sv - current_sv).abs() > current_sv * 0.05 {
+            changes[window] += 1;
+        }
+        current_sv = sv;
+        last_window = window;
+    }
+    if !(0.5..=2.0).contains(&current_sv) {
+        for g in &mut gimmick[last_window..] {
+            *g = true;
+        }
+    }
+
+    for (g, &c) in gimmick.iter_mut().zip(&changes) {
+        *g |= c >= 3;
+    }
+    gimmick
+}
+
+fn analyze_preprocessed(pre: &Preprocessed, timings: &[OsuTimingPoint]) -> PatternReport {
+    let rows = build_rows(&pre.note_seq);
+    let window_count = ((pre.t as f64 / WINDOW_LENGTH).ceil() as usize).max(1);
+    let sv_gimmick = sv_gimmick_windows(timings, window_count);
+
+    let mut window_labels: Vec<Vec<Pattern>> = Vec::with_capacity(window_count);
+    let mut active_windows = 0;
+    let mut row_index = 0;
+    for (i, &gimmick) in sv_gimmick.iter().enumerate() {
+        let start = (i as f64 * WINDOW_LENGTH) as u32;
+        let end = ((i + 1) as f64 * WINDOW_LENGTH) as u32;
+        let start_index = row_index;
+        while row_index < rows.len() && rows[row_index].0 < end {
+            row_index += 1;
+        }
+        let window_rows = &rows[start_index..row_index];
+        let notes: usize = window_rows.iter().map(|r| r.1.count_ones() as usize).sum();
+        let held = pre.ln_seq.iter().any(|&(_, h, t)| h < end && t > start);
+
+        let mut labels = Vec::new();
+        if notes > 0 || held {
+            active_windows += 1;
+            let prev_mask = start_index.checked_sub(1).map(|j| rows[j].1);
+            classify_rows(window_rows, prev_mask, pre.k, &mut labels);
+            classify_lns(pre, start, end, notes, &mut labels);
+            if gimmick {
+                labels.push(Pattern::SvGimmick);
+            }
+        }
+        window_labels.push(labels);
+    }
+
+    // 合并相邻分段得到时间段，并统计覆盖率
+    let mut coverage: Vec<(Pattern, f64)> = Vec::new();
+    let mut sections: Vec<PatternSection> = Vec::new();
+    for pattern in Pattern::ALL {
+        let mut count = 0;
+        let mut current: Option<PatternSection> = None;
+        for (i, labels) in window_labels.iter().enumerate() {
+            if labels.contains(&pattern) {
+                count += 1;
+                let (start, end) = (i as f64 * WINDOW_LENGTH, (i + 1) as f64 * WINDOW_LENGTH);
+                match current.as_mut() {
+                    Some(s) if s.end == start => s.end = end,
+                    _ => {
+                        sections.extend(current.take());
+                        current = Some(PatternSection {
+                            pattern,
+                            start,
+                            end,
+                        });
+                    }
+                }
+            }
+        }
+        sections.extend(current);
+        if count > 0 {
+            coverage.push((pattern, count as f64 / active_windows as f64 * 100.0));
+        }
+    }
+    coverage.sort_by(|a, b| b.1.total_cmp(&a.1));
+    sections.sort_by(|a, b| a.start.total_cmp(&b.start));
+
+    PatternReport { coverage, sections }
+}
+
+/// 分析谱面的键型分布
+pub fn analyze_patterns(data: &OsuDataLegacy) -> io::Result<PatternReport> {
+    if data.misc.circle_size > 32 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Pattern analysis supports at most 32 columns",
+        ));
+    }
+    let pre = preprocess(data, &Mods::default())?;
+    Ok(analyze_preprocessed(&pre, &data.timings))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn timing(time: f64, val: f64, is_timing: bool) -> OsuTimingPoint {
+        OsuTimingPoint {
+            time,
+            val,
+            is_timing,
+        }
+    }
+
+    #[test]
+    fn sv_gimmick_windows_accepts_unsorted_timings() {
+        let timings = [
+            timing(100.0, 500.0, true),
+            timing(20000.0, -10.0, false),
+            timing(5000.0, -100.0, false),
+        ];
+        let gimmick = sv_gimmick_windows(&timings, 30);
+        // 10x 的 SV 从 20 秒开始持续到结尾
+        assert!(!gimmick[..20].iter().any(|&g| g));
+        assert!(gimmick[20..].iter().all(|&g| g));
+    }
+}
@@ -0,0 +1,265 @@
+use crate::osu_func::Mods;
+
+/// pp 公式
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PpFormula {
+    /// 当前（2022 年重做后）的公式，只与星级、物件数与准确率有关
+    Current,
+    /// 重做前基于分数 (ScoreV1) 的公式
+    Legacy,
+}
+
+/// 各判定的数量
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Judgements {
+    pub max: u32,
+    pub n300: u32,
+    pub n200: u32,
+    pub n100: u32,
+    pub n50: u32,
+    pub miss: u32,
+}
+
+/// 各判定 (MAX, 300, 200, 100, 50, miss) 的分值
+const HIT_VALUES: [f64; 6] = [320.0, 300.0, 200.0, 100.0, 50.0, 0.0];
+/// ScoreV1 中各判定的奖励分值与奖励值的变化
+const LEGACY_BONUS: [(f64, f64); 6] = [
+    (32.0, 2.0),
+    (32.0, 1.0),
+    (16.0, -8.0),
+    (8.0, -24.0),
+    (4.0, -44.0),
+    (0.0, -100.0),
+];
+
+impl Judgements {
+    fn counts(&self) -> [u32; 6] {
+        [
+            self.max, self.n300, self.n200, self.n100, self.n50, self.miss,
+        ]
+    }
+
+    pub fn total(&self) -> u32 {
+        self.counts().iter().sum()
+    }
+
+    /// MAX 按 320 计的准确率（与 lazer 及当前 pp 公式一致），范围 0~1
+    pub fn accuracy(&self) -> f64 {
+        let total = self.total();
+        if total == 0 {
+            return 0.0;
+        }
+        let points: f64 = self
+            .counts()
+            .iter()
+            .zip(HIT_VALUES)
+            .map(|(&n, v)| n as f64 * v)
+            .sum();
+        points / (total as f64 * 320.0)
+    }
+
+    /// MAX 与 300 同样按 300 计的准确率（osu!stable 显示的准确率），范围 0~1
+    pub fn legacy_accuracy(&self) -> f64 {
+        let total = self.total();
+        if total == 0 {
+            return 0.0;
+        }
+        let points = 300.0 * (self.max + self.n300) as f64
+            + 200.0 * self.n200 as f64
+            + 100.0 * self.n100 as f64
+            + 50.0 * self.n50 as f64;
+        points / (total as f64 * 300.0)
+    }
+
+    /// 按 `accuracy` 的定义构造一组判定：从 MAX 开始，依次用更差的判定补足准确率的差距。
+    /// 例如 98% 只包含 MAX 与 300，准确率更低时才会出现 200 及以下的判定。
+    pub fn from_accuracy(accuracy: f64, total: u32) -> Self {
+        let accuracy = accuracy.clamp(0.0, 1.0);
+        let deficit = (1.0 - accuracy) * 320.0 * total as f64;
+        let mut counts = [0u32; 6];
+        counts[0] = total;
+        for j in 1..HIT_VALUES.len() {
+            let loss = 320.0 - HIT_VALUES[j];
+            if deficit <= loss * total as f64 || j == HIT_VALUES.len() - 1 {
+                // 只用第 j - 1 与第 j 种判定组合出所需的差距
+                let prev_loss = 320.0 - HIT_VALUES[j - 1];
+                let n = ((deficit - prev_loss * total as f64) / (loss - prev_loss))
+                    .round()
+                    .clamp(0.0, total as f64) as u32;
+                counts = [0; 6];
+                counts[j] = n;
+                counts[j - 1] = total - n;
+                break;
+            }
+        }
+        Self {
+            max: counts[0],
+            n300: counts[1],
+            n200: counts[2],
+            n100: counts[3],
+            n50: counts[4],
+            miss: counts[5],
+        }
+    }
+
+    /// 估算 ScoreV1 分数（不含 Mod 倍率，满分 1,000,000）。
+    /// 奖励分与判定顺序有关，这里假设各判定均匀分布在整首歌中。
+    pub fn legacy_score(&self) -> f64 {
+        let counts = self.counts();
+        let total = self.total();
+        if total == 0 {
+            return 0.0;
+        }
+        let mut used = [0u32; 6];
+        let mut bonus: f64 = 100.0;
+        let mut base_score = 0.0;
+        let mut bonus_score = 0.0;
+        for i in 1..=total {
+            // 选择目前最 "欠缺" 的判定
+            let j = (0..6)
+                .filter(|&j| used[j] < counts[j])
+                .max_by(|&a, &b| {
+                    let lag =
+                        |j: usize| counts[j] as f64 * i as f64 / total as f64 - used[j] as f64;
+                    lag(a).total_cmp(&lag(b))
+                })
+                .unwrap_or(0);
+            used[j] += 1;
+            let (bonus_value, bonus_change) = LEGACY_BONUS[j];
+            bonus = (bonus + bonus_change).clamp(0.0, 100.0);
+            base_score += HIT_VALUES[j] / 320.0;
+            bonus_score += bonus_value * bonus.sqrt() / 320.0;
+        }
+        500_000.0 * (base_score + bonus_score) / total as f64
+    }
+}
+
+/// 计算 pp 所需的谱面属性
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PpAttributes {
+    /// 已考虑 `mods` 的星级
+    pub sr: f64,
+    pub od: f64,
+    /// 物件数（单键与面条的总数）
+    pub object_count: u32,
+    pub mods: Mods,
+}
+
+impl PpAttributes {
+    pub fn new(sr: f64, od: f64, object_count: u32, mods: Mods) -> Self {
+        Self {
+            sr,
+            od,
+            object_count,
+            mods,
+        }
+    }
+
+    fn length_bonus(&self) -> f64 {
+        1.0 + 0.1 * (self.object_count as f64 / 1500.0).min(1.0)
+    }
+
+    fn current_pp(&self, accuracy: f64) -> f64 {
+        let mut multiplier = 8.0;
+        if self.mods.no_fail {
+            multiplier *= 0.75;
+        }
+        if self.mods.easy {
+            multiplier *= 0.5;
+        }
+        (self.sr - 0.15).max(0.05).powf(2.2)
+            * (5.0 * accuracy - 4.0).max(0.0)
+            * self.length_bonus()
+            * multiplier
+    }
+
+    /// 旧公式中的 300 判定区间（毫秒）。
+    /// 旧版计算在 DT / HT 下先乘以 1.5 / 0.75 再除以倍速，两者抵消，因此只随 HR / EZ 缩放
+    fn legacy_great_window(&self) -> f64 {
+        ((64.0 - 3.0 * self.od).floor() * self.mods.window_factor()).ceil()
+    }
+
+    fn legacy_pp(&self, score: f64) -> f64 {
+        let mut strain = (5.0 * (self.sr / 0.2).max(1.0) - 4.0).powf(2.2) / 135.0;
+        strain *= self.length_bonus();
+        strain *= match score {
+            s if s <= 500_000.0 => 0.0,
+            s if s <= 600_000.0 => (s - 500_000.0) / 100_000.0 * 0.3,
+            s if s <= 700_000.0 => 0.3 + (s - 600_000.0) / 100_000.0 * 0.25,
+            s if s <= 800_000.0 => 0.55 + (s - 700_000.0) / 100_000.0 * 0.2,
+            s if s <= 900_000.0 => 0.75 + (s - 800_000.0) / 100_000.0 * 0.15,
+            s => 0.9 + (s - 900_000.0) / 100_000.0 * 0.1,
+        };
+
+        let acc_value = (0.2 - (self.legacy_great_window() - 34.0) * 0.006667).max(0.0)
+            * strain
+            * ((score - 960_000.0).max(0.0) / 40_000.0).powf(1.1);
+
+        let mut multiplier = 0.8;
+        if self.mods.no_fail {
+            multiplier *= 0.9;
+        }
+        if self.mods.easy {
+            multiplier *= 0.5;
+        }
+        (strain.powf(1.1) + acc_value.powf(1.1)).powf(1.0 / 1.1) * multiplier
+    }
+
+    /// 根据判定数量计算 pp
+    pub fn pp(&self, judgements: &Judgements, formula: PpFormula) -> f64 {
+        match formula {
+            PpFormula::Current => self.current_pp(judgements.accuracy()),
+            PpFormula::Legacy => self.legacy_pp(judgements.legacy_score()),
+        }
+    }
+
+    /// 根据准确率（0~1，定义同 `Judgements::accuracy`）计算 pp
+    pub fn pp_for_accuracy(&self, accuracy: f64, formula: PpFormula) -> f64 {
+        match formula {
+            PpFormula::Current => self.current_pp(accuracy.clamp(0.0, 1.0)),
+            PpFormula::Legacy => self.pp(
+                &Judgements::from_accuracy(accuracy, self.object_count.max(1)),
+                formula,
+            ),
+        }
+    }
+
+    /// 达到 `target_pp` 所需的最低准确率，满准确率也无法达到时返回 None
+    pub fn required_accuracy(&self, target_pp: f64, formula: PpFormula) -> Option<f64> {
+        if self.pp_for_accuracy(1.0, formula) < target_pp {
+            return None;
+        }
+        if target_pp <= 0.0 {
+            return Some(0.0);
+        }
+        match formula {
+            PpFormula::Current => {
+                let max_pp = self.current_pp(1.0);
+                // pp 与 (5 × acc - 4) 成正比
+                Some(((target_pp / max_pp + 4.0) / 5.0).clamp(0.0, 1.0))
+            }
+            PpFormula::Legacy => {
+                let (mut low, mut high) = (0.0, 1.0);
+                for _ in 0..40 {
+                    let mid = (low + high) / 2.0;
+                    if self.pp_for_accuracy(mid, formula) >= target_pp {
+                        high = mid;
+                    } else {
+                        low = mid;
+                    }
+                }
+                Some(high)
+            }
+        }
+    }
+
+    /// 95% ~ 100% 准确率下的 pp，每 1% 一项，格式为 (准确率百分比, pp)
+    pub fn pp_table(&self, formula: PpFormula) -> Vec<(f64, f64)> {
+        (95..=100)
+            .map(|acc| {
+                let acc = acc as f64;
+                (acc, self.pp_for_accuracy(acc / 100.0, formula))
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,252 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::io;
+
+use super::calc_sr::{preprocess, Preprocessed};
+use crate::osu_func::{Mods, OsuDataLegacy};
+
+/// 分段长度（毫秒），与 MinaCalc 相同
+const INTERVAL_LENGTH: f64 = 500.0;
+/// 取难度最高的这一比例的分段作为评分依据
+const TOP_PROPORTION: f64 = 0.3;
+/// 将 "等效 NPS" 换算为接近 Etterna MSD 的数值
+const MSD_SCALE: f64 = 1.35;
+
+/// 类 Etterna (MinaSD) 的 4K 技能评分。
+/// 算法为简化的近似实现，数值量级与 MSD 接近，但不保证与 Etterna 一致。
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct SkillsetRatings {
+    pub overall: f64,
+    pub stream: f64,
+    pub jumpstream: f64,
+    pub handstream: f64,
+    pub stamina: f64,
+    pub jackspeed: f64,
+    pub chordjack: f64,
+    pub technical: f64,
+}
+
+impl SkillsetRatings {
+    /// 除 Overall 外的所有技能，按评分从高到低排列
+    pub fn sorted(&self) -> Vec<(&'static str, f64)> {
+        let mut skills = vec![
+            ("Stream", self.stream),
+            ("Jumpstream", self.jumpstream),
+            ("Handstream", self.handstream),
+            ("Stamina", self.stamina),
+            ("JackSpeed", self.jackspeed),
+            ("Chordjack", self.chordjack),
+            ("Technical", self.technical),
+        ];
+        skills.sort_by(|a, b| b.1.total_cmp(&a.1));
+        skills
+    }
+}
+
+impl fmt::Display for SkillsetRatings {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let skills: Vec<String> = self
+            .sorted()
+            .iter()
+            .map(|(name, v)| format!("{} {:.2}", name, v))
+            .collect();
+        write!(f, "Overall {:.2} ({})", self.overall, skills.join(", "))
+    }
+}
+
+/// 单个分段内各技能的等效 NPS
+#[derive(Debug, Clone, Copy, Default)]
+struct IntervalSkills {
+    stream: f64,
+    jumpstream: f64,
+    handstream: f64,
+    jackspeed: f64,
+    chordjack: f64,
+    technical: f64,
+}
+
+impl IntervalSkills {
+    fn max(&self) -> f64 {
+        [
+            self.stream,
+            self.jumpstream,
+            self.handstream,
+            self.jackspeed,
+            self.chordjack,
+            self.technical,
+        ]
+        .into_iter()
+        .fold(0.0, f64::max)
+    }
+}
+
+/// 同一时刻的音符组成一行，记录为 (时间, 列的位掩码)
+fn build_rows(note_seq: &[(u32, u32, i32)]) -> Vec<(u32, u8)> {
+    let mut rows: Vec<(u32, u8)> = Vec::new();
+    for &(k, h, _) in note_seq {
+        match rows.last_mut() {
+            Some((time, mask)) if *time == h => *mask |= 1 << k,
+            _ => rows.push((h, 1 << k)),
+        }
+    }
+    rows
+}
+
+fn interval_skills(rows: &[(u32, u8)], prev_row: Option<(u32, u8)>, jack_nps: f64) -> IntervalSkills {
+    let notes: u32 = rows.iter().map(|(_, m)| m.count_ones()).sum();
+    if notes == 0 {
+        return IntervalSkills::default();
+    }
+    let nps = notes as f64 * 1000.0 / INTERVAL_LENGTH;
+
+    // 按多押大小统计音符占比
+    let mut by_size = [0.0; 5];
+    for (_, mask) in rows {
+        by_size[mask.count_ones() as usize] += mask.count_ones() as f64;
+    }
+    let total = notes as f64;
+    let (single, jump, hand, quad) = (
+        by_size[1] / total,
+        by_size[2] / total,
+        by_size[3] / total,
+        by_size[4] / total,
+    );
+
+    // 与前一行共用列的音符（叠键）占比，以及其中属于多押的部分
+    let mut jack_notes = 0;
+    let mut chord_jack_notes = 0;
+    let mut prev_mask = prev_row.map(|(_, m)| m);
+    for &(_, mask) in rows {
+        if let Some(prev) = prev_mask {
+            let shared = (mask & prev).count_ones();
+            jack_notes += shared;
+            if mask.count_ones() >= 2 {
+                chord_jack_notes += mask.count_ones().min(shared * 2);
+            }
+        }
+        prev_mask = Some(mask);
+    }
+    let jack_ratio = jack_notes as f64 / total;
+    let chord_jack_ratio = chord_jack_notes as f64 / total;
+
+    // 行间隔的离散程度，用于估计节奏的复杂度
+    let mut times: Vec<u32> = prev_row.iter().map(|r| r.0).collect();
+    times.extend(rows.iter().map(|r| r.0));
+    let gaps: Vec<f64> = times.windows(2).map(|w| (w[1] - w[0]) as f64).collect();
+    let irregularity = if gaps.len() >= 2 {
+        let mean = gaps.iter().sum::<f64>() / gaps.len() as f64;
+        let variance = gaps.iter().map(|g| (g - mean).powi(2)).sum::<f64>() / gaps.len() as f64;
+        (variance.sqrt() / mean.max(1.0)).min(1.0)
+    } else {
+        0.0
+    };
+
+    IntervalSkills {
+        stream: nps * (single + 0.5 * jump) * (1.0 - jack_ratio),
+        jumpstream: nps
+            * (jump + 0.5 * hand + 0.3 * single)
+            * (jump * 3.0).min(1.0)
+            * (1.0 - 0.5 * jack_ratio),
+        handstream: nps
+            * (hand + 0.6 * quad + 0.3 * jump)
+            * ((hand + quad) * 3.0).min(1.0)
+            * (1.0 - 0.5 * jack_ratio),
+        jackspeed: jack_nps * 2.5,
+        chordjack: nps * (0.4 + chord_jack_ratio) * ((1.0 - single) * 1.5).min(1.0),
+        technical: nps * (0.5 + 0.5 * irregularity) * (1.0 - jack_ratio) * (single + jump),
+    }
+}
+
+/// 取最高的一部分分段的平均值
+fn aggregate(values: &[f64]) -> f64 {
+    let mut sorted: Vec<f64> = values.iter().copied().filter(|v| *v > 0.0).collect();
+    if sorted.is_empty() {
+        return 0.0;
+    }
+    sorted.sort_by(|a, b| b.total_cmp(a));
+    let count = ((sorted.len() as f64 * TOP_PROPORTION).ceil() as usize)
+        .max(4)
+        .min(sorted.len());
+    sorted[..count].iter().sum::<f64>() / count as f64
+}
+
+fn calculate_from_preprocessed(pre: &Preprocessed) -> SkillsetRatings {
+    let rows = build_rows(&pre.note_seq);
+    let interval_count = (pre.t as f64 / INTERVAL_LENGTH).ceil() as usize;
+
+    // 每个分段内单列的最大密度（叠键速度）。
+    // 只统计相邻两行之间的叠键，中间隔一行的减半，避免把普通的连打算作叠键
+    let row_index_of = |time: u32| rows.partition_point(|r| r.0 < time);
+    let mut jack_nps = vec![0.0; interval_count.max(1)];
+    for column in &pre.note_seq_by_column {
+        for pair in column.windows(2) {
+            let weight = match row_index_of(pair[1].1) - row_index_of(pair[0].1) {
+                1 => 1.0,
+                2 => 0.5,
+                _ => continue,
+            };
+            let gap = pair[1].1.saturating_sub(pair[0].1).max(1) as f64;
+            let i = ((pair[1].1 as f64 / INTERVAL_LENGTH) as usize).min(jack_nps.len() - 1);
+            jack_nps[i] = f64::max(jack_nps[i], weight * 1000.0 / gap);
+        }
+    }
+
+    let mut intervals: Vec<IntervalSkills> = Vec::with_capacity(interval_count);
+    let mut row_index = 0;
+    for (i, &jack) in jack_nps.iter().enumerate() {
+        let end = ((i + 1) as f64 * INTERVAL_LENGTH) as u32;
+        let start_index = row_index;
+        while row_index < rows.len() && rows[row_index].0 < end {
+            row_index += 1;
+        }
+        let prev_row = start_index.checked_sub(1).map(|j| rows[j]);
+        intervals.push(interval_skills(&rows[start_index..row_index], prev_row, jack));
+    }
+
+    let collect = |f: fn(&IntervalSkills) -> f64| {
+        aggregate(&intervals.iter().map(f).collect::<Vec<f64>>()) * MSD_SCALE
+    };
+    let stream = collect(|s| s.stream);
+    let jumpstream = collect(|s| s.jumpstream);
+    let handstream = collect(|s| s.handstream);
+    let jackspeed = collect(|s| s.jackspeed);
+    let chordjack = collect(|s| s.chordjack);
+    let technical = collect(|s| s.technical);
+
+    // 耐力：持续处于高难度的时长越长，加成越高
+    let peak_values: Vec<f64> = intervals.iter().map(|s| s.max()).collect();
+    let base = aggregate(&peak_values);
+    let sustained_secs = peak_values
+        .iter()
+        .filter(|&&v| base > 0.0 && v >= base * 0.8)
+        .count() as f64
+        * INTERVAL_LENGTH
+        / 1000.0;
+    let stamina =
+        base * MSD_SCALE * (0.8 + 0.1 * (1.0 + sustained_secs / 60.0).log2()).min(1.05);
+
+    let mut ratings = SkillsetRatings {
+        overall: 0.0,
+        stream,
+        jumpstream,
+        handstream,
+        stamina,
+        jackspeed,
+        chordjack,
+        technical,
+    };
+    ratings.overall = ratings.sorted().first().map_or(0.0, |(_, v)| *v);
+    ratings
+}
+
+/// 计算 4K 谱面的技能评分
+pub fn calculate_skillsets(data: &OsuDataLegacy, mods: &Mods) -> io::Result<SkillsetRatings> {
+    if data.misc.circle_size != 4 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Skillset ratings are only available for 4K charts",
+        ));
+    }
+    let pre = preprocess(data, mods)?;
+    Ok(calculate_from_preprocessed(&pre))
+}
@@ -0,0 +1,273 @@
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+use crate::osu_func::OsuDataLegacy;
+use crate::transform::sv::dominant_bpm;
+
+/// 计算 NPS 的滑动窗口长度（毫秒）
+const NPS_WINDOW: u32 = 1000;
+/// 相邻两行的间隔不超过此值（毫秒）时视为连续的 stream
+const STREAM_MAX_GAP: u32 = 150;
+/// 谱面没有标注休息段时，超过此长度（毫秒）的空白视为休息段
+const BREAK_MIN_GAP: u32 = 5000;
+
+/// 最长的一段连续 stream
+#[derive(Debug, Clone, Copy, Default, PartialEq, Serialize, Deserialize)]
+pub struct StreamSection {
+    pub start: u32,
+    pub end: u32,
+    /// 包含的音符数（叠键按多个计）
+    pub note_count: u32,
+}
+
+/// 谱面统计信息
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ChartStats {
+    /// 按游玩时间（去除休息段）计算的平均 NPS
+    pub average_nps: f64,
+    /// 滑动窗口内的最高 NPS
+    pub peak_nps: f64,
+    /// 最高 NPS 所在窗口的起始时刻
+    pub peak_nps_time: u32,
+    /// 下标为叠键数量，值为该大小的行数，下标 0 不使用
+    pub chord_histogram: Vec<u32>,
+    /// 每列的音符数（含面条）
+    pub column_counts: Vec<u32>,
+    /// 左手负责的音符比例，奇数键的中间列左右各算一半
+    pub left_hand_ratio: f64,
+    /// 至少有一个面条被按住的时间占游玩时间的比例，范围 0~1
+    pub ln_coverage: f64,
+    pub longest_stream: Option<StreamSection>,
+    /// 第一个音符开始到最后一个音符结束的时长（毫秒）
+    pub total_time: u32,
+    /// 去除休息段后的游玩时间（毫秒）
+    pub drain_time: u32,
+    /// 持续时间最长的 BPM
+    pub dominant_bpm: Option<f64>,
+}
+
+impl ChartStats {
+    pub fn right_hand_ratio(&self) -> f64 {
+        1.0 - self.left_hand_ratio
+    }
+
+    /// 形如 "1: 320 | 2: 140 | 3: 12" 的叠键统计
+    pub fn chord_string(&self) -> String {
+        let items: Vec<String> = self
+            .chord_histogram
+            .iter()
+            .enumerate()
+            .skip(1)
+            .filter(|(_, &n)| n > 0)
+            .map(|(size, n)| format!("{}: {}", size, n))
+            .collect();
+        items.join(" | ")
+    }
+}
+
+fn format_time(ms: u32) -> String {
+    format!("{}:{:02}", ms / 60000, (ms % 60000) / 1000)
+}
+
+impl fmt::Display for ChartStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "NPS: {:.2} avg, {:.2} peak at {}",
+            self.average_nps,
+            self.peak_nps,
+            format_time(self.peak_nps_time)
+        )?;
+        writeln!(
+            f,
+            "Drain: {} / Total: {}",
+            format_time(self.drain_time),
+            format_time(self.total_time)
+        )?;
+        if let Some(bpm) = self.dominant_bpm {
+            writeln!(f, "Dominant BPM: {:.1}", bpm)?;
+        }
+        writeln!(f, "Chords: {}", self.chord_string())?;
+        let columns: Vec<String> = self.column_counts.iter().map(|n| n.to_string()).collect();
+        writeln!(
+            f,
+            "Columns: {} (L {:.1}% / R {:.1}%)",
+            columns.join(" "),
+            self.left_hand_ratio * 100.0,
+            self.right_hand_ratio() * 100.0
+        )?;
+        write!(f, "LN Coverage: {:.1}%", self.ln_coverage * 100.0)?;
+        if let Some(stream) = self.longest_stream {
+            write!(
+                f,
+                "\nLongest Stream: {} notes ({} - {})",
+                stream.note_count,
+                format_time(stream.start),
+                format_time(stream.end)
+            )?;
+        }
+        Ok(())
+    }
+}
+
+/// 合并有重叠的区间，返回按起点排序的不相交区间
+fn merge_intervals(mut intervals: Vec<(u32, u32)>) -> Vec<(u32, u32)> {
+    intervals.retain(|(s, e)| e > s);
+    intervals.sort_unstable();
+    let mut merged: Vec<(u32, u32)> = Vec::with_capacity(intervals.len());
+    for (s, e) in intervals {
+        match merged.last_mut() {
+            Some(last) if s <= last.1 => last.1 = last.1.max(e),
+            _ => merged.push((s, e)),
+        }
+    }
+    merged
+}
+
+/// 区间与 [start, end) 重叠部分的总长度
+fn covered_length(intervals: &[(u32, u32)], start: u32, end: u32) -> u32 {
+    intervals
+        .iter()
+        .map(|&(s, e)| e.min(end).saturating_sub(s.max(start)))
+        .sum()
+}
+
+/// 计算谱面统计信息，Malody 谱面可先通过 `McData::to_osu_data` 转换
+pub fn calculate_chart_stats(data: &OsuDataLegacy) -> ChartStats {
+    let column_count = data.misc.circle_size.max(1);
+    let mut stats = ChartStats {
+        chord_histogram: vec![0; column_count as usize + 1],
+        column_counts: vec![0; column_count as usize],
+        ..Default::default()
+    };
+    if data.notes.is_empty() {
+        return stats;
+    }
+
+    // (列, 开始, 结束)
+    let mut notes: Vec<(u32, u32, u32)> = data
+        .notes
+        .iter()
+        .map(|n| {
+            let column = (n.x_pos * column_count / 512).min(column_count - 1);
+            (column, n.time, n.end_time.unwrap_or(n.time).max(n.time))
+        })
+        .collect();
+    notes.sort_unstable_by_key(|&(_, start, _)| start);
+
+    let first_time = notes[0].1;
+    let last_time = notes
+        .iter()
+        .map(|&(_, _, end)| end)
+        .max()
+        .unwrap_or(first_time);
+    stats.total_time = last_time - first_time;
+    stats.dominant_bpm = dominant_bpm(&data.timings, last_time as f64);
+
+    // 列与左右手
+    for &(column, _, _) in &notes {
+        stats.column_counts[column as usize] += 1;
+    }
+    let half = column_count as f64 / 2.0;
+    let left: f64 = stats
+        .column_counts
+        .iter()
+        .enumerate()
+        .map(|(i, &n)| n as f64 * (half - i as f64).clamp(0.0, 1.0))
+        .sum();
+    stats.left_hand_ratio = left / notes.len() as f64;
+
+    // 按开始时刻分行
+    let mut rows: Vec<(u32, u32)> = Vec::new(); // (时刻, 音符数)
+    for &(_, start, _) in &notes {
+        match rows.last_mut() {
+            Some(row) if row.0 == start => row.1 += 1,
+            _ => rows.push((start, 1)),
+        }
+    }
+    for &(_, size) in &rows {
+        let size = (size as usize).min(column_count as usize);
+        stats.chord_histogram[size] += 1;
+    }
+
+    // 最长 stream：相邻行间隔不超过 STREAM_MAX_GAP 的最长连续段
+    let mut best: Option<StreamSection> = None;
+    let mut begin = 0;
+    for i in 1..=rows.len() {
+        if i < rows.len() && rows[i].0 - rows[i - 1].0 <= STREAM_MAX_GAP {
+            continue;
+        }
+        if i - begin > 1 {
+            let section = StreamSection {
+                start: rows[begin].0,
+                end: rows[i - 1].0,
+                note_count: rows[begin..i].iter().map(|&(_, n)| n).sum(),
+            };
+            if best.map_or(0, |b| b.note_count) < section.note_count {
+                best = Some(section);
+            }
+        }
+        begin = i;
+    }
+    stats.longest_stream = best;
+
+    // 峰值 NPS：以每个音符为窗口起点的滑动窗口
+    let starts: Vec<u32> = notes.iter().map(|&(_, start, _)| start).collect();
+    let mut end_index = 0;
+    for (i, &start) in starts.iter().enumerate() {
+        while end_index < starts.len() && starts[end_index] < start + NPS_WINDOW {
+            end_index += 1;
+        }
+        let nps = (end_index - i) as f64 * 1000.0 / NPS_WINDOW as f64;
+        if nps > stats.peak_nps {
+            stats.peak_nps = nps;
+            stats.peak_nps_time = start;
+        }
+    }
+
+    // 休息段：优先使用谱面中标注的休息段，否则取较长的空白
+    let breaks = if data.misc.breaks.is_empty() {
+        let mut gaps = Vec::new();
+        let mut held_until = notes[0].2;
+        for &(_, start, end) in &notes[1..] {
+            if start >= held_until + BREAK_MIN_GAP {
+                gaps.push((held_until, start));
+            }
+            held_until = held_until.max(end);
+        }
+        gaps
+    } else {
+        data.misc
+            .breaks
+            .iter()
+            .map(|&(s, e)| (s.max(0) as u32, e.max(0) as u32))
+            .collect()
+    };
+    let breaks = merge_intervals(breaks);
+    stats.drain_time = stats
+        .total_time
+        .saturating_sub(covered_length(&breaks, first_time, last_time));
+    if stats.drain_time > 0 {
+        stats.average_nps = notes.len() as f64 * 1000.0 / stats.drain_time as f64;
+    }
+
+    // 面条覆盖率
+    let ln_intervals = merge_intervals(
+        notes
+            .iter()
+            .filter(|&&(_, start, end)| end > start)
+            .map(|&(_, start, end)| (start, end))
+            .collect(),
+    );
+    // 休息段内被按住的面条不计入
+    let ln_in_breaks: u32 = ln_intervals
+        .iter()
+        .map(|&(s, e)| covered_length(&breaks, s, e))
+        .sum();
+    let ln_time = covered_length(&ln_intervals, first_time, last_time).saturating_sub(ln_in_breaks);
+    if stats.drain_time > 0 {
+        stats.ln_coverage = (ln_time as f64 / stats.drain_time as f64).min(1.0);
+    }
+
+    stats
+}
@@ -0,0 +1,37 @@
+pub mod background;
+pub mod key_convert;
+pub mod normalize;
+pub mod practice;
+pub mod rate;
+pub mod sv;
+
+pub use self::background::{BackgroundFit, BackgroundOptions};
+pub use self::key_convert::{KeyConvertOptions, KeyConvertReport, RemovedNote};
+pub use self::normalize::{NormalizeChange, NormalizeOptions, NormalizeReport};
+pub use self::practice::{hardest_section, PracticeOptions, SectionRange};
+pub use self::rate::{add_rated_difficulties, format_rate};
+pub use self::sv::SvOperation;
+
+/// 简单的可复现随机数生成器 (SplitMix64)，用于需要种子的谱面变换
+pub(crate) struct SeededRng {
+    state: u64,
+}
+
+impl SeededRng {
+    pub(crate) fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub(crate) fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E37_79B9_7F4A_7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+        z ^ (z >> 31)
+    }
+
+    /// [0, 1) 区间内的浮点数
+    pub(crate) fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 / (1u64 << 53) as f64
+    }
+}
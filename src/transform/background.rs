@@ -0,0 +1,201 @@
+use image::{imageops::FilterType, DynamicImage, ImageReader, RgbImage};
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+use crate::graphx::{render_title_image, OutputFormat};
+use crate::misc::sanitize_filename;
+use crate::osu_func::OsuMisc;
+
+/// 用于生成不重复的临时文件名
+static TEMP_COUNTER: AtomicU64 = AtomicU64::new(0);
+
+/// 将背景图调整为目标比例的方式
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum BackgroundFit {
+    /// 居中裁剪多余的部分
+    #[default]
+    Crop,
+    /// 保留完整图片，空白处以黑色填充
+    Letterbox,
+}
+
+/// 背景图规范化选项
+#[derive(Debug, Clone)]
+pub struct BackgroundOptions {
+    pub fit: BackgroundFit,
+    /// 最大宽度，同时决定输出比例；较小的图片不会被放大
+    pub width: u32,
+    pub height: u32,
+    pub jpeg_quality: u8,
+    /// 背景图缺失或无法解码时，生成标题图作为背景
+    pub generate_fallback: bool,
+}
+
+impl Default for BackgroundOptions {
+    fn default() -> Self {
+        Self {
+            fit: BackgroundFit::Crop,
+            width: 1920,
+            height: 1080,
+            jpeg_quality: 90,
+            generate_fallback: true,
+        }
+    }
+}
+
+/// 将图片裁剪或填充为目标比例，并在超过目标大小时缩小
+pub fn fit_background(image: &DynamicImage, options: &BackgroundOptions) -> RgbImage {
+    let image = image.to_rgb8();
+    let (src_w, src_h) = (image.width().max(1), image.height().max(1));
+    let target_ratio = options.width.max(1) as f64 / options.height.max(1) as f64;
+    let src_ratio = src_w as f64 / src_h as f64;
+
+    match options.fit {
+        BackgroundFit::Crop => {
+            // 居中裁剪到目标比例
+            let (crop_w, crop_h) = if src_ratio > target_ratio {
+                ((src_h as f64 * target_ratio).round() as u32, src_h)
+            } else {
+                (src_w, (src_w as f64 / target_ratio).round() as u32)
+            };
+            let (crop_w, crop_h) = (crop_w.clamp(1, src_w), crop_h.clamp(1, src_h));
+            let cropped = image::imageops::crop_imm(
+                &image,
+                (src_w - crop_w) / 2,
+                (src_h - crop_h) / 2,
+                crop_w,
+                crop_h,
+            )
+            .to_image();
+            if crop_w > options.width {
+                image::imageops::resize(
+                    &cropped,
+                    options.width,
+                    options.height,
+                    FilterType::CatmullRom,
+                )
+            } else {
+                cropped
+            }
+        }
+        BackgroundFit::Letterbox => {
+            // 先缩小到目标大小以内，再居中放到目标比例的画布上
+            let scale = (options.width as f64 / src_w as f64)
+                .min(options.height as f64 / src_h as f64)
+                .min(1.0);
+            let (fit_w, fit_h) = (
+                ((src_w as f64 * scale).round() as u32).max(1),
+                ((src_h as f64 * scale).round() as u32).max(1),
+            );
+            let fitted = if scale < 1.0 {
+                image::imageops::resize(&image, fit_w, fit_h, FilterType::CatmullRom)
+            } else {
+                image
+            };
+            let (canvas_w, canvas_h) = if fit_w as f64 / fit_h as f64 > target_ratio {
+                (fit_w, (fit_w as f64 / target_ratio).round() as u32)
+            } else {
+                ((fit_h as f64 * target_ratio).round() as u32, fit_h)
+            };
+            let (canvas_w, canvas_h) = (canvas_w.max(fit_w), canvas_h.max(fit_h));
+            let mut canvas = RgbImage::new(canvas_w, canvas_h);
+            image::imageops::overlay(
+                &mut canvas,
+                &fitted,
+                ((canvas_w - fit_w) / 2) as i64,
+                ((canvas_h - fit_h) / 2) as i64,
+            );
+            canvas
+        }
+    }
+}
+
+/// 解码背景图，调整比例与大小后以 JPEG 保存到 `dest`
+pub fn normalize_background(
+    src: &Path,
+    dest: &Path,
+    options: &BackgroundOptions,
+) -> io::Result<()> {
+    let image = ImageReader::open(src)?
+        .with_guessed_format()?
+        .decode()
+        .map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))?;
+    let fitted = fit_background(&image, options);
+    let mut buffer = Vec::new();
+    image::codecs::jpeg::JpegEncoder::new_with_quality(
+        &mut buffer,
+        options.jpeg_quality.clamp(1, 100),
+    )
+    .encode_image(&fitted)
+    .map_err(io::Error::other)?;
+    write_atomic(dest, &buffer)
+}
+
+/// 生成信息卡风格的标题图作为背景，保存到 `dest`
+pub fn write_fallback_background(
+    misc: &OsuMisc,
+    dest: &Path,
+    options: &BackgroundOptions,
+) -> io::Result<()> {
+    let content = render_title_image(
+        misc,
+        options.width,
+        options.height,
+        OutputFormat::Jpeg {
+            quality: options.jpeg_quality,
+        },
+    )?;
+    write_atomic(dest, &content)
+}
+
+/// 处理 `dir` 中谱面的背景图：规范化已有的背景图，或在缺失时生成标题图。
+/// 成功时更新 `misc.background` 并返回新背景图的路径；背景图无法使用且不生成替代背景时返回 None
+pub fn prepare_background(
+    dir: &Path,
+    misc: &mut OsuMisc,
+    options: &BackgroundOptions,
+) -> io::Result<Option<PathBuf>> {
+    if !misc.background.is_empty() {
+        let src = dir.join(&misc.background);
+        if src.is_file() {
+            let stem = Path::new(&misc.background)
+                .file_stem()
+                .map_or("bg".into(), |s| s.to_string_lossy().into_owned());
+            let name = sanitize_filename(&format!("{}_bg.jpg", stem));
+            let dest = dir.join(&name);
+            match normalize_background(&src, &dest, options) {
+                Ok(()) => {
+                    misc.background = name;
+                    return Ok(Some(dest));
+                }
+                Err(e) => eprintln!("Failed to normalize background {}: {}", src.display(), e),
+            }
+        }
+    }
+    if !options.generate_fallback {
+        return Ok(None);
+    }
+    // 同一谱面集的难度共用一张标题图，文件名取标题与曲师的哈希以避开非 ASCII 字符
+    let digest = md5::compute(format!("{}\n{}", misc.title, misc.artist));
+    let name = format!("title_{}.jpg", &format!("{:x}", digest)[..8]);
+    let dest = dir.join(&name);
+    write_fallback_background(misc, &dest, options)?;
+    misc.background = name;
+    Ok(Some(dest))
+}
+
+/// 先写入临时文件再重命名，避免并行处理多个难度时读到写了一半的图片
+fn write_atomic(dest: &Path, content: &[u8]) -> io::Result<()> {
+    let temp_path = dest.with_extension(format!(
+        "{}.{}.tmp",
+        std::process::id(),
+        TEMP_COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    fs::write(&temp_path, content)?;
+    fs::rename(&temp_path, dest).inspect_err(|_| {
+        let _ = fs::remove_file(&temp_path);
+    })
+}
@@ -0,0 +1,279 @@
+use std::cmp::Ordering;
+use std::io;
+
+use super::SeededRng;
+use crate::malody_func::McData;
+use crate::osu_func::{HitObject, OsuData};
+
+/// osu!stable 支持的最大键数
+const MAX_KEYS: u32 = 10;
+/// 在目标列上制造原谱不存在的叠键时的惩罚
+const JACK_PENALTY: f64 = 16.0;
+/// 原谱有叠键且沿用同一目标列时的奖励
+const JACK_BONUS: f64 = 2.0;
+
+#[derive(Debug, Clone)]
+pub struct KeyConvertOptions {
+    pub target_keys: u32,
+    /// 扩展键数时分配列使用的随机种子，相同种子得到相同结果
+    pub seed: u64,
+}
+
+/// 转换过程中被移除的音符。
+/// osu! 谱面的时间单位为毫秒，Malody 谱面的时间单位为拍。
+#[derive(Debug, Clone)]
+pub struct RemovedNote {
+    pub time: f64,
+    pub end_time: Option<f64>,
+    pub column: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct KeyConvertReport {
+    pub from_keys: u32,
+    pub to_keys: u32,
+    pub kept: usize,
+    /// 与同一时刻其他音符合并（多押超出目标键数）而移除的音符
+    pub merged: Vec<RemovedNote>,
+    /// 没有可用的列（被面条占用或会产生新的叠键）而丢弃的音符
+    pub dropped: Vec<RemovedNote>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ColumnNote {
+    column: u32,
+    start: f64,
+    end: Option<f64>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Assignment {
+    Column(u32),
+    Merged,
+    Dropped,
+}
+
+fn check_key_counts(from_keys: u32, to_keys: u32) -> io::Result<()> {
+    if from_keys == 0 || from_keys > MAX_KEYS {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unsupported source key count: {}", from_keys),
+        ));
+    }
+    if to_keys == 0 || to_keys > MAX_KEYS {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Unsupported target key count: {}", to_keys),
+        ));
+    }
+    Ok(())
+}
+
+/// 为每个音符分配目标列。
+/// 按时间顺序逐个多押组处理：优先选择与原位置对应的列，
+/// 避开被面条占用的列，并尽量不制造原谱中不存在的叠键。
+/// `eps` 为判定同一多押的时间容差。
+fn assign_columns(
+    notes: &[ColumnNote],
+    from_keys: u32,
+    to_keys: u32,
+    seed: u64,
+    eps: f64,
+) -> Vec<Assignment> {
+    if from_keys == to_keys {
+        return notes.iter().map(|n| Assignment::Column(n.column)).collect();
+    }
+
+    let mut order: Vec<usize> = (0..notes.len()).collect();
+    order.sort_by(|&a, &b| {
+        notes[a]
+            .start
+            .partial_cmp(&notes[b].start)
+            .unwrap_or(Ordering::Equal)
+            .then(notes[a].column.cmp(&notes[b].column))
+    });
+
+    let to_u = to_keys as usize;
+    let ratio = to_keys as f64 / from_keys as f64;
+    let expanding = to_keys > from_keys;
+    let mut rng = SeededRng::new(seed);
+
+    let mut assignments = vec![Assignment::Dropped; notes.len()];
+    let mut busy_until = vec![f64::NEG_INFINITY; to_u];
+    let mut column_last_group: Vec<Option<usize>> = vec![None; to_u];
+    let mut source_last_group: Vec<Option<usize>> = vec![None; from_keys as usize];
+    let mut source_last_target: Vec<Option<u32>> = vec![None; from_keys as usize];
+
+    let mut group = 0usize;
+    let mut group_start = f64::NEG_INFINITY;
+    let mut used_in_group = vec![false; to_u];
+    let mut kept_in_group = 0usize;
+
+    for &i in &order {
+        let note = notes[i];
+        let source = note.column as usize;
+
+        if note.start - group_start > eps {
+            if group_start.is_finite() {
+                group += 1;
+            }
+            group_start = note.start;
+            used_in_group.iter_mut().for_each(|u| *u = false);
+            kept_in_group = 0;
+        }
+
+        let previous_group = group.checked_sub(1);
+        let source_jack =
+            previous_group.is_some() && source_last_group[source] == previous_group;
+        let ideal = (note.column as f64 + 0.5) * ratio - 0.5;
+
+        let best = (0..to_keys)
+            .filter(|&c| !used_in_group[c as usize] && busy_until[c as usize] < note.start - eps)
+            .map(|c| {
+                let mut score = (c as f64 - ideal).abs();
+                if expanding {
+                    // 在原列对应的区间内随机分散
+                    score += rng.next_f64() * ratio;
+                }
+                let target_jack =
+                    previous_group.is_some() && column_last_group[c as usize] == previous_group;
+                if source_jack && source_last_target[source] == Some(c) {
+                    score -= JACK_BONUS;
+                } else if target_jack {
+                    score += JACK_PENALTY;
+                }
+                (c, score)
+            })
+            .min_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal));
+
+        source_last_group[source] = Some(group);
+        match best {
+            Some((c, _)) => {
+                let c_u = c as usize;
+                used_in_group[c_u] = true;
+                busy_until[c_u] = note.end.unwrap_or(note.start).max(note.start);
+                column_last_group[c_u] = Some(group);
+                source_last_target[source] = Some(c);
+                kept_in_group += 1;
+                assignments[i] = Assignment::Column(c);
+            }
+            None if kept_in_group > 0 => assignments[i] = Assignment::Merged,
+            None => assignments[i] = Assignment::Dropped,
+        }
+    }
+
+    assignments
+}
+
+fn build_report(
+    notes: &[ColumnNote],
+    assignments: &[Assignment],
+    from_keys: u32,
+    to_keys: u32,
+) -> KeyConvertReport {
+    let mut report = KeyConvertReport {
+        from_keys,
+        to_keys,
+        kept: 0,
+        merged: Vec::new(),
+        dropped: Vec::new(),
+    };
+    for (note, assignment) in notes.iter().zip(assignments) {
+        let removed = RemovedNote {
+            time: note.start,
+            end_time: note.end,
+            column: note.column,
+        };
+        match assignment {
+            Assignment::Column(_) => report.kept += 1,
+            Assignment::Merged => report.merged.push(removed),
+            Assignment::Dropped => report.dropped.push(removed),
+        }
+    }
+    report
+}
+
+impl<H> OsuData<H>
+where
+    H: HitObject + Clone,
+{
+    /// 将谱面转换为指定键数，返回被合并或丢弃的音符
+    pub fn convert_keys(&mut self, options: &KeyConvertOptions) -> io::Result<KeyConvertReport> {
+        let from_keys = self.misc.circle_size;
+        let to_keys = options.target_keys;
+        check_key_counts(from_keys, to_keys)?;
+
+        let notes: Vec<ColumnNote> = self
+            .notes
+            .iter()
+            .map(|n| ColumnNote {
+                column: (n.get_x_pos() * from_keys / 512).min(from_keys - 1),
+                start: n.get_time().into(),
+                end: n.get_end_time().map(|t| t.into()),
+            })
+            .collect();
+
+        let assignments = assign_columns(&notes, from_keys, to_keys, options.seed, 0.5);
+        let report = build_report(&notes, &assignments, from_keys, to_keys);
+
+        let column_factor = 512.0 / to_keys as f64;
+        let old_notes = std::mem::take(&mut self.notes);
+        self.notes = old_notes
+            .into_iter()
+            .zip(assignments)
+            .filter_map(|(mut note, assignment)| match assignment {
+                Assignment::Column(c) => {
+                    note.set_x_pos(((c as f64 + 0.5) * column_factor).floor() as u32);
+                    Some(note)
+                }
+                _ => None,
+            })
+            .collect();
+        self.misc.circle_size = to_keys;
+
+        Ok(report)
+    }
+}
+
+impl McData {
+    /// 将谱面转换为指定键数，返回被合并或丢弃的音符（时间单位为拍）
+    pub fn convert_keys(&mut self, options: &KeyConvertOptions) -> io::Result<KeyConvertReport> {
+        let from_keys = self.meta.mode_ext.column as u32;
+        let to_keys = options.target_keys;
+        check_key_counts(from_keys, to_keys)?;
+
+        // 音频信息等不含列号的音符不参与转换
+        let (indices, notes): (Vec<usize>, Vec<ColumnNote>) = self
+            .note
+            .iter()
+            .enumerate()
+            .filter_map(|(i, n)| {
+                let column = n.column? as u32;
+                Some((
+                    i,
+                    ColumnNote {
+                        column: column.min(from_keys - 1),
+                        start: n.beat_to_float(),
+                        end: n.endbeat.as_ref().map(|_| n.end_beat_to_float()),
+                    },
+                ))
+            })
+            .unzip();
+
+        let assignments = assign_columns(&notes, from_keys, to_keys, options.seed, 1e-6);
+        let report = build_report(&notes, &assignments, from_keys, to_keys);
+
+        let mut keep = vec![true; self.note.len()];
+        for (&i, assignment) in indices.iter().zip(&assignments) {
+            match assignment {
+                Assignment::Column(c) => self.note[i].column = Some(*c as u8),
+                _ => keep[i] = false,
+            }
+        }
+        let mut keep_iter = keep.into_iter();
+        self.note.retain(|_| keep_iter.next().unwrap_or(true));
+        self.meta.mode_ext.column = to_keys as u8;
+
+        Ok(report)
+    }
+}
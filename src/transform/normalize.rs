@@ -0,0 +1,199 @@
+use std::cmp::Ordering;
+
+use crate::osu_func::{HitObject, OsuData, OsuTimingPoint};
+
+const TIME_EPSILON: f64 = 1e-6;
+
+#[derive(Debug, Clone)]
+pub struct NormalizeOptions {
+    /// 允许吸附的节拍细分，吸附到最近的拍线，距离相同时优先使用靠前的细分
+    pub divisors: Vec<u32>,
+    /// 吸附的最大偏移（毫秒），超出则保持原时间
+    pub tolerance: f64,
+}
+
+impl Default for NormalizeOptions {
+    fn default() -> Self {
+        Self {
+            divisors: vec![1, 2, 4, 3, 6, 8, 12, 16],
+            tolerance: 2.0,
+        }
+    }
+}
+
+/// 规范化过程中的一项修改
+#[derive(Debug, Clone)]
+pub enum NormalizeChange {
+    /// 音符头或面条尾被吸附到拍线
+    Snapped {
+        column: u32,
+        is_end: bool,
+        from: f64,
+        to: f64,
+        divisor: u32,
+    },
+    /// 长度为零或为负的面条被改为普通音符
+    LnFixed {
+        column: u32,
+        time: f64,
+        end_time: f64,
+    },
+    /// 删除了完全相同的时间点
+    TimingDeduped { time: f64, is_timing: bool },
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NormalizeReport {
+    /// 音符原本不是按时间排序的
+    pub notes_reordered: bool,
+    /// 时间点原本不是按时间排序的
+    pub timings_reordered: bool,
+    pub changes: Vec<NormalizeChange>,
+}
+
+impl NormalizeReport {
+    pub fn is_empty(&self) -> bool {
+        !self.notes_reordered && !self.timings_reordered && self.changes.is_empty()
+    }
+}
+
+fn cmp_timing(a: &OsuTimingPoint, b: &OsuTimingPoint) -> Ordering {
+    a.time
+        .partial_cmp(&b.time)
+        .unwrap_or(Ordering::Equal)
+        .then(b.is_timing.cmp(&a.is_timing))
+}
+
+/// 找到 `time` 最近的合法拍线，返回 (吸附后的时间, 细分)
+fn snap_time(
+    red_lines: &[&OsuTimingPoint],
+    time: f64,
+    options: &NormalizeOptions,
+) -> Option<(f64, u32)> {
+    // 稍早于红线的音符也按该红线吸附
+    let red = red_lines
+        .iter()
+        .rev()
+        .find(|t| t.time <= time + options.tolerance)
+        .or(red_lines.first())?;
+    if red.val <= 0.0 {
+        return None;
+    }
+    // 取距离最近的拍线，距离相同时优先使用 `divisors` 中靠前的细分
+    options
+        .divisors
+        .iter()
+        .filter(|&&d| d > 0)
+        .filter_map(|&d| {
+            let step = red.val / d as f64;
+            let snapped = red.time + ((time - red.time) / step).round() * step;
+            ((snapped - time).abs() <= options.tolerance).then_some((snapped, d))
+        })
+        .fold(None, |best: Option<(f64, u32)>, candidate| match best {
+            // 同一拍线由不同细分算出时可能有浮点误差
+            Some(b) if (b.0 - time).abs() <= (candidate.0 - time).abs() + TIME_EPSILON => Some(b),
+            _ => Some(candidate),
+        })
+}
+
+impl<H> OsuData<H>
+where
+    H: HitObject + Clone,
+{
+    /// 排序音符与时间点，删除重复时间点，将音符吸附到当前红线的拍线上，
+    /// 并把长度不为正的面条改为普通音符。返回所有修改。
+    pub fn normalize(&mut self, options: &NormalizeOptions) -> NormalizeReport {
+        // 时间点：排序并去重
+        let mut report = NormalizeReport {
+            timings_reordered: self
+                .timings
+                .windows(2)
+                .any(|w| cmp_timing(&w[0], &w[1]) == Ordering::Greater),
+            ..Default::default()
+        };
+        self.timings.sort_by(cmp_timing);
+        let mut timings: Vec<OsuTimingPoint> = Vec::with_capacity(self.timings.len());
+        for tp in self.timings.drain(..) {
+            let duplicated = timings
+                .iter()
+                .rev()
+                .take_while(|t| (t.time - tp.time).abs() < TIME_EPSILON)
+                .any(|t| t.is_timing == tp.is_timing && (t.val - tp.val).abs() < TIME_EPSILON);
+            if duplicated {
+                report.changes.push(NormalizeChange::TimingDeduped {
+                    time: tp.time,
+                    is_timing: tp.is_timing,
+                });
+            } else {
+                timings.push(tp);
+            }
+        }
+        self.timings = timings;
+
+        let column_count = self.misc.circle_size.max(1);
+        let red_lines: Vec<&OsuTimingPoint> = self.timings.iter().filter(|t| t.is_timing).collect();
+
+        for note in self.notes.iter_mut() {
+            let column = (note.get_x_pos() * column_count / 512).min(column_count - 1);
+
+            let time: f64 = note.get_time().into();
+            if let Some((snapped, divisor)) = snap_time(&red_lines, time, options) {
+                note.set_time(snapped);
+                let new_time: f64 = note.get_time().into();
+                if (new_time - time).abs() > TIME_EPSILON {
+                    report.changes.push(NormalizeChange::Snapped {
+                        column,
+                        is_end: false,
+                        from: time,
+                        to: new_time,
+                        divisor,
+                    });
+                }
+            }
+
+            let end_time: Option<f64> = note.get_end_time().map(|t| t.into());
+            if let Some(end_time) = end_time {
+                if let Some((snapped, divisor)) = snap_time(&red_lines, end_time, options) {
+                    note.set_end_time(Some(snapped));
+                    let new_end: f64 = note.get_end_time().map_or(snapped, |t| t.into());
+                    if (new_end - end_time).abs() > TIME_EPSILON {
+                        report.changes.push(NormalizeChange::Snapped {
+                            column,
+                            is_end: true,
+                            from: end_time,
+                            to: new_end,
+                            divisor,
+                        });
+                    }
+                }
+            }
+
+            let time: f64 = note.get_time().into();
+            if let Some(end_time) = note.get_end_time().map(|t| t.into()) {
+                if end_time <= time {
+                    note.set_end_time(None);
+                    report.changes.push(NormalizeChange::LnFixed {
+                        column,
+                        time,
+                        end_time,
+                    });
+                }
+            }
+        }
+
+        // 音符：按时间、列排序
+        let cmp_note = |a: &H, b: &H| {
+            let (ta, tb): (f64, f64) = (a.get_time().into(), b.get_time().into());
+            ta.partial_cmp(&tb)
+                .unwrap_or(Ordering::Equal)
+                .then(a.get_x_pos().cmp(&b.get_x_pos()))
+        };
+        report.notes_reordered = self
+            .notes
+            .windows(2)
+            .any(|w| cmp_note(&w[0], &w[1]) == Ordering::Greater);
+        self.notes.sort_by(cmp_note);
+
+        report
+    }
+}
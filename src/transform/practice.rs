@@ -0,0 +1,343 @@
+use std::cmp::Ordering;
+use std::io;
+
+use crate::malody_func::{Effect, McData, Timing};
+use crate::osu_func::{
+    calculate_curve, calculate_from_data, HitObject, OsuData, OsuDataLegacy, OsuTimingPoint,
+};
+
+/// 练习段落的范围。osu! 的拍数从第一根红线开始计算。
+#[derive(Debug, Clone, Copy)]
+pub enum SectionRange {
+    /// 毫秒 [start, end)
+    Time(f64, f64),
+    /// 拍数 [start, end)
+    Beat(f64, f64),
+}
+
+#[derive(Debug, Clone)]
+pub struct PracticeOptions {
+    pub range: SectionRange,
+    /// 段落前保留的准备时间（毫秒），期间没有音符
+    pub lead_in: f64,
+    /// 段落重复次数，至少为 1。
+    /// 由于音频共享，只有第一遍与音乐对齐，之后的重复按整拍接在段落之后。
+    pub repeat: u32,
+}
+
+fn check_section(start: f64, end: f64) -> io::Result<()> {
+    if !(start.is_finite() && end.is_finite()) || end <= start {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid section range: {} - {}", start, end),
+        ));
+    }
+    Ok(())
+}
+
+fn format_section_time(ms: f64) -> String {
+    let ms = ms.max(0.0) as u32;
+    format!("{}:{:02}", ms / 60000, (ms % 60000) / 1000)
+}
+
+/// osu! 拍数转换为毫秒，第 0 拍位于第一根红线
+fn osu_beat_to_time(red_lines: &[&OsuTimingPoint], beat: f64) -> f64 {
+    let mut time = red_lines[0].time;
+    let mut prev_beat = 0.0;
+    let mut interval = red_lines[0].val;
+    for red in red_lines.iter().skip(1) {
+        let red_beat = prev_beat + (red.time - time) / interval;
+        if red_beat >= beat {
+            break;
+        }
+        time = red.time;
+        prev_beat = red_beat;
+        interval = red.val;
+    }
+    time + (beat - prev_beat) * interval
+}
+
+impl<H> OsuData<H>
+where
+    H: HitObject + Clone,
+{
+    /// 截取谱面中的一段作为新难度。
+    /// 截取点处会重新放置当前生效的红线（对齐到拍线）和绿线，预览点设为段落开始。
+    pub fn extract_section(&self, options: &PracticeOptions) -> io::Result<Self> {
+        let mut timings = self.timings.clone();
+        timings.sort_by(|a, b| {
+            a.time
+                .partial_cmp(&b.time)
+                .unwrap_or(Ordering::Equal)
+                .then(b.is_timing.cmp(&a.is_timing))
+        });
+        let red_lines: Vec<&OsuTimingPoint> = timings.iter().filter(|t| t.is_timing).collect();
+        if red_lines.is_empty() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "No timing points in chart!",
+            ));
+        }
+
+        let (start, end) = match options.range {
+            SectionRange::Time(a, b) => (a, b),
+            SectionRange::Beat(a, b) => (
+                osu_beat_to_time(&red_lines, a),
+                osu_beat_to_time(&red_lines, b),
+            ),
+        };
+        check_section(start, end)?;
+
+        let cut = start - options.lead_in.max(0.0);
+        let red = red_lines
+            .iter()
+            .rev()
+            .find(|t| t.time <= cut)
+            .unwrap_or(&red_lines[0]);
+        // 新红线对齐到原拍线，保证小节线位置不变
+        let aligned = red.time + ((cut - red.time) / red.val).floor() * red.val;
+        let sv = timings
+            .iter()
+            .rev()
+            .find(|t| !t.is_timing && t.time >= red.time && t.time <= cut);
+
+        let mut pass_timings = vec![OsuTimingPoint {
+            time: aligned,
+            val: red.val,
+            is_timing: true,
+        }];
+        if let Some(sv) = sv {
+            pass_timings.push(OsuTimingPoint {
+                time: aligned,
+                val: sv.val,
+                is_timing: false,
+            });
+        }
+        pass_timings.extend(
+            timings
+                .iter()
+                .filter(|t| t.time > cut && t.time < end)
+                .cloned(),
+        );
+
+        let pass_notes: Vec<H> = self
+            .notes
+            .iter()
+            .filter(|n| {
+                let t: f64 = n.get_time().into();
+                t >= start && t < end
+            })
+            .cloned()
+            .collect();
+
+        let period = ((end - aligned) / red.val).ceil() * red.val;
+        let mut data = self.clone();
+        data.timings = Vec::new();
+        data.notes = Vec::new();
+        for pass in 0..options.repeat.max(1) {
+            let shift = pass as f64 * period;
+            data.timings
+                .extend(pass_timings.iter().map(|t| OsuTimingPoint {
+                    time: t.time + shift,
+                    val: t.val,
+                    is_timing: t.is_timing,
+                }));
+            data.notes.extend(pass_notes.iter().cloned().map(|mut n| {
+                let time: f64 = n.get_time().into();
+                let end_time: Option<f64> = n.get_end_time().map(|t| t.into());
+                if pass > 0 {
+                    n.set_time(time + shift);
+                    n.set_end_time(end_time.map(|t| t + shift));
+                }
+                n
+            }));
+        }
+
+        // 截取点早于音频开头时，用 AudioLeadIn 补足
+        if cut < 0.0 {
+            data.misc.audio_lead_in = data.misc.audio_lead_in.max((-cut).ceil() as i32);
+        }
+        data.misc.preview_time = start.round() as i32;
+        data.misc.breaks.retain(|&(s, e)| s as f64 >= start && (e as f64) < end);
+        data.misc.version = format!(
+            "{} (Practice {}-{})",
+            self.misc.version,
+            format_section_time(start),
+            format_section_time(end)
+        );
+        data.misc.beatmap_id = 0;
+
+        Ok(data)
+    }
+}
+
+fn shift_beat(beat: &[u32], shift: u32) -> Vec<u32> {
+    vec![beat[0] + shift, beat[1], beat[2]]
+}
+
+impl McData {
+    /// 截取谱面中的一段作为新难度（拍数不变，音频共享）。
+    /// 截取点之前的 BPM 变化会保留以维持与音频的同步，变速效果在截取点重新放置。
+    pub fn extract_section(&self, options: &PracticeOptions) -> io::Result<Self> {
+        let (start, end) = match options.range {
+            SectionRange::Beat(a, b) => (a, b),
+            SectionRange::Time(a, b) => (self.time_to_beat(a), self.time_to_beat(b)),
+        };
+        check_section(start, end)?;
+
+        let cut = self.time_to_beat(self.beat_to_time(start) - options.lead_in.max(0.0));
+        let aligned = cut.floor().max(0.0) as u32;
+        let aligned_f = aligned as f64;
+
+        let active_bpm = self
+            .time
+            .iter()
+            .rev()
+            .find(|t| t.beat_to_float() <= aligned_f)
+            .or(self.time.first())
+            .map(|t| t.bpm)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "Missing BPM data"))?;
+        let pass_bpms: Vec<&Timing> = self
+            .time
+            .iter()
+            .filter(|t| {
+                let b = t.beat_to_float();
+                b > aligned_f && b < end
+            })
+            .collect();
+
+        let effects = self.effect.clone().unwrap_or_default();
+        let active_scroll = effects
+            .iter()
+            .rev()
+            .find(|e| e.beat_to_float() <= aligned_f)
+            .map(|e| e.scroll);
+        let pass_effects: Vec<&Effect> = effects
+            .iter()
+            .filter(|e| {
+                let b = e.beat_to_float();
+                b > aligned_f && b < end
+            })
+            .collect();
+
+        let (mut pass_notes, sound_notes): (Vec<_>, Vec<_>) = self
+            .note
+            .iter()
+            .filter(|n| {
+                let b = n.beat_to_float();
+                n.column.is_none() || (b >= start && b < end)
+            })
+            .cloned()
+            .partition(|n| n.column.is_some());
+        pass_notes.sort_by(|a, b| {
+            a.beat_to_float()
+                .partial_cmp(&b.beat_to_float())
+                .unwrap_or(Ordering::Equal)
+        });
+
+        let period = (end - aligned_f).ceil() as u32;
+        let mut data = self.clone();
+        // 截取点之前的 BPM 保留，保证后续拍数与音频对应
+        data.time = self
+            .time
+            .iter()
+            .filter(|t| t.beat_to_float() <= aligned_f)
+            .cloned()
+            .collect();
+        let mut new_effects = Vec::new();
+        data.note = Vec::new();
+
+        for pass in 0..options.repeat.max(1) {
+            let shift = pass * period;
+            if pass > 0 {
+                data.time.push(Timing {
+                    beat: vec![aligned + shift, 0, 1],
+                    bpm: active_bpm,
+                });
+            }
+            data.time.extend(pass_bpms.iter().map(|t| Timing {
+                beat: shift_beat(&t.beat, shift),
+                bpm: t.bpm,
+            }));
+            if let Some(scroll) = active_scroll {
+                new_effects.push(Effect {
+                    beat: vec![aligned + shift, 0, 1],
+                    scroll,
+                });
+            }
+            new_effects.extend(pass_effects.iter().map(|e| Effect {
+                beat: shift_beat(&e.beat, shift),
+                scroll: e.scroll,
+            }));
+            data.note.extend(pass_notes.iter().cloned().map(|mut n| {
+                n.beat = shift_beat(&n.beat, shift);
+                n.endbeat = n.endbeat.map(|e| shift_beat(&e, shift));
+                n
+            }));
+        }
+        // 音频信息音符保持在最后
+        data.note.extend(sound_notes);
+        data.effect = if new_effects.is_empty() {
+            None
+        } else {
+            Some(new_effects)
+        };
+
+        let start_time = self.beat_to_time(start);
+        data.meta.preview = Some((start_time + self.audio_offset()).round() as i32);
+        data.meta.version = format!(
+            "{} (Practice {}-{})",
+            self.meta.version,
+            format_section_time(start_time),
+            format_section_time(self.beat_to_time(end))
+        );
+
+        Ok(data)
+    }
+}
+
+/// 以 `step` 为步长滑动长度为 `length` 的窗口，返回难度最高的时间段 (start, end, sr)。
+/// 窗口根据难度曲线选取，最后只对选中的时间段计算一次 SR。
+/// 窗口长度取最接近 `length` 的 `step` 整数倍，返回的时间段与计算 SR 的时间段一致。
+pub fn hardest_section(
+    data: &OsuDataLegacy,
+    length: f64,
+    step: f64,
+) -> io::Result<(f64, f64, f64)> {
+    if !(length > 0.0 && step > 0.0) {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "Section length and step must be positive",
+        ));
+    }
+    if data.notes.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "No notes in chart!"));
+    }
+    let curve = calculate_curve(data, 1.0, step)?;
+    let window = ((length / step).round() as usize).clamp(1, curve.difficulty.len());
+
+    // 窗口内难度的五次幂之和，与 SR 的加权平均一致，更突出峰值
+    let mut prefix = vec![0.0; curve.difficulty.len() + 1];
+    for (i, d) in curve.difficulty.iter().enumerate() {
+        prefix[i + 1] = prefix[i] + d.powf(5.0);
+    }
+    let best = (0..=curve.difficulty.len() - window)
+        .max_by(|&a, &b| {
+            let sum_a = prefix[a + window] - prefix[a];
+            let sum_b = prefix[b + window] - prefix[b];
+            sum_a.partial_cmp(&sum_b).unwrap_or(Ordering::Equal)
+        })
+        .unwrap_or(0);
+
+    let start = best as f64 * step;
+    let end = start + window as f64 * step;
+    let mut section = data.clone();
+    section
+        .notes
+        .retain(|n| n.time as f64 >= start && (n.time as f64) < end);
+    if section.notes.is_empty() {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, "No notes in chart!"));
+    }
+    let sr = calculate_from_data(&section, 1.0)?;
+    Ok((start, end, sr))
+}
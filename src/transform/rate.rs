@@ -0,0 +1,206 @@
+use std::collections::{HashMap, HashSet};
+use std::fs::{self, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipWriter};
+
+use crate::audio::AudioBackend;
+use crate::osu_func::osz_func::extract_archive;
+use crate::osu_func::{HitObject, OsuData, OsuDataV128};
+
+/// 倍速的显示字符串，如 1.2、0.75
+pub fn format_rate(rate: f64) -> String {
+    format!("{:.2}", rate)
+        .trim_end_matches('0')
+        .trim_end_matches('.')
+        .to_string()
+}
+
+fn check_rate(rate: f64) -> io::Result<()> {
+    if !rate.is_finite() || rate <= 0.0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("Invalid rate: {}", rate),
+        ));
+    }
+    Ok(())
+}
+
+impl<H> OsuData<H>
+where
+    H: HitObject + Clone,
+{
+    /// 生成 `rate` 倍速的新难度：缩放所有音符、时间点、预览时间和休息段，
+    /// 并在难度名后追加倍速（如 "Insane 1.2x"）。音频文件名需要调用方另行设置。
+    pub fn with_rate(&self, rate: f64) -> io::Result<Self> {
+        check_rate(rate)?;
+        let mut data = self.clone();
+        let scale = |t: f64| t / rate;
+
+        for note in data.notes.iter_mut() {
+            let time: f64 = note.get_time().into();
+            let end_time: Option<f64> = note.get_end_time().map(|t| t.into());
+            note.set_time(scale(time));
+            note.set_end_time(end_time.map(scale));
+        }
+
+        for tp in data.timings.iter_mut() {
+            tp.time = scale(tp.time);
+            // 绿线为相对倍率，不随倍速变化
+            if tp.is_timing {
+                tp.val /= rate;
+            }
+        }
+
+        if data.misc.preview_time >= 0 {
+            data.misc.preview_time = scale(data.misc.preview_time as f64).round() as i32;
+        }
+        for (start, end) in data.misc.breaks.iter_mut() {
+            *start = scale(*start as f64).round() as i32;
+            *end = scale(*end as f64).round() as i32;
+        }
+
+        data.misc.version = format!("{} {}x", data.misc.version, format_rate(rate));
+        data.misc.beatmap_id = 0;
+        Ok(data)
+    }
+}
+
+/// 为 .osz 中的每个难度生成指定倍速的版本，并与原难度一起重新打包到原 .osz 中。
+/// 每个 (音频, 倍速) 组合只会经过 `backend` 处理一次，
+/// `backend` 无法处理的组合会输出警告并跳过对应的难度。
+pub fn add_rated_difficulties(
+    osz_path: &Path,
+    rates: &[f64],
+    backend: &dyn AudioBackend,
+) -> io::Result<PathBuf> {
+    for &rate in rates {
+        check_rate(rate)?;
+    }
+
+    let temp_dir = tempdir::TempDir::new("rate_osz")?;
+    let temp_dir_path = temp_dir.path();
+    extract_archive(osz_path, temp_dir_path)?;
+
+    let mut files: HashSet<PathBuf> = WalkDir::new(temp_dir_path)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.path().to_path_buf())
+        .collect();
+
+    let osu_paths: Vec<PathBuf> = files
+        .iter()
+        .filter(|p| p.extension() == Some(std::ffi::OsStr::new("osu")))
+        .cloned()
+        .collect();
+
+    // (原音频路径, 倍速字符串) -> 变速后的音频文件名，处理失败时为 None
+    let mut rated_audio: HashMap<(String, String), Option<String>> = HashMap::new();
+
+    for osu_path in &osu_paths {
+        let osu_path_str = osu_path.to_string_lossy();
+        let data = match OsuDataV128::from_file(&osu_path_str) {
+            Ok(d) => d,
+            Err(e) => {
+                eprintln!("Cannot get osu data from {}: {e}", osu_path_str);
+                continue;
+            }
+        };
+
+        for &rate in rates {
+            let rate_str = format_rate(rate);
+            let mut rated = data.with_rate(rate)?;
+
+            // 谱面中的文件名相对于 .osu 所在的目录
+            let song_dir = osu_path.parent().unwrap_or(temp_dir_path);
+            let audio_path = song_dir.join(&data.misc.audio_file_name);
+            let key = (audio_path.to_string_lossy().into_owned(), rate_str.clone());
+            if !rated_audio.contains_key(&key) {
+                let stem = audio_path
+                    .file_stem()
+                    .map(|s| s.to_string_lossy().into_owned())
+                    .unwrap_or_else(|| "audio".into());
+                let audio_name = format!(
+                    "{}_{}x.{}",
+                    stem,
+                    rate_str,
+                    backend.output_extension(&audio_path)
+                );
+                let rated_audio_path = song_dir.join(&audio_name);
+                // 音频无法处理时跳过该倍速，不影响其他已生成的难度
+                let result = match backend.change_rate(&audio_path, &rated_audio_path, rate) {
+                    Ok(()) => {
+                        files.insert(rated_audio_path);
+                        Some(audio_name)
+                    }
+                    Err(e) => {
+                        eprintln!(
+                            "Cannot change rate of {} to {}x: {e}",
+                            audio_path.display(),
+                            rate_str
+                        );
+                        None
+                    }
+                };
+                rated_audio.insert(key.clone(), result);
+            }
+            let Some(audio_name) = rated_audio[&key].clone() else {
+                continue;
+            };
+            rated.misc.audio_file_name = audio_name;
+
+            let osu_stem = osu_path
+                .file_stem()
+                .map(|s| s.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let rated_osu_path = song_dir.join(format!("{} [{}x].osu", osu_stem, rate_str));
+            println!("Generating .osu file at: {:?}", rated_osu_path);
+            rated.to_file(&rated_osu_path.to_string_lossy())?;
+            files.insert(rated_osu_path);
+        }
+    }
+
+    // 先写入同目录下的临时文件，完成后再替换原 .osz
+    let packed_path = osz_path.with_extension("osz.tmp");
+    let result = pack_dir(&packed_path, temp_dir_path, &files)
+        .and_then(|_| fs::rename(&packed_path, osz_path));
+    if result.is_err() {
+        let _ = fs::remove_file(&packed_path);
+    }
+    result?;
+
+    Ok(osz_path.to_path_buf())
+}
+
+/// 将 `root` 下的文件以相对于 `root` 的路径打包，保留原有的目录层级
+fn pack_dir(zip_path: &Path, root: &Path, files: &HashSet<PathBuf>) -> io::Result<()> {
+    let mut sorted_files: Vec<_> = files.iter().collect();
+    sorted_files.sort();
+
+    let mut zip_writer = ZipWriter::new(File::create(zip_path)?);
+    for path in sorted_files {
+        let relative_path = path.strip_prefix(root).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("File outside of the archive dir: {}", path.display()),
+            )
+        })?;
+        let entry_name = relative_path
+            .components()
+            .map(|c| c.as_os_str().to_string_lossy())
+            .collect::<Vec<_>>()
+            .join("/");
+
+        let mut file = File::open(path)?;
+        zip_writer.start_file(
+            entry_name,
+            SimpleFileOptions::default().compression_method(CompressionMethod::Stored),
+        )?;
+        io::copy(&mut file, &mut zip_writer)?;
+    }
+    zip_writer.finish()?;
+    Ok(())
+}
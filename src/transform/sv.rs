@@ -0,0 +1,288 @@
+use std::cmp::Ordering;
+
+use crate::malody_func::{Effect, Timing};
+use crate::osu_func::OsuTimingPoint;
+
+/// osu! 绿线的 SV 倍率与 beatLength 互相转换
+fn sv_from_val(val: f64) -> f64 {
+    -100.0 / val
+}
+
+fn val_from_sv(sv: f64) -> f64 {
+    -100.0 / sv
+}
+
+const SV_EPSILON: f64 = 1e-6;
+
+/// 可在转换流程中使用的变速处理
+#[derive(Debug, Clone, Copy)]
+pub enum SvOperation {
+    /// 删除所有绿线 / 变速效果
+    Strip,
+    /// 以指定 BPM 为基准抵消 BPM 变化带来的流速变化，`None` 时使用持续时间最长的 BPM
+    Normalize { base_bpm: Option<f64> },
+    /// 将变速倍率限制在 [min, max]
+    Clamp { min: f64, max: f64 },
+    /// 合并与当前倍率相同的冗余绿线
+    MergeRedundant,
+}
+
+fn sort_timings(timings: &mut [OsuTimingPoint]) {
+    // 同一时刻红线在前，绿线在后覆盖
+    timings.sort_by(|a, b| {
+        a.time
+            .partial_cmp(&b.time)
+            .unwrap_or(Ordering::Equal)
+            .then(b.is_timing.cmp(&a.is_timing))
+    });
+}
+
+/// 持续时间最长的 BPM，`end_time` 为谱面结束时刻
+pub fn dominant_bpm(timings: &[OsuTimingPoint], end_time: f64) -> Option<f64> {
+    let mut red_lines: Vec<&OsuTimingPoint> = timings.iter().filter(|t| t.is_timing).collect();
+    red_lines.sort_by(|a, b| a.time.partial_cmp(&b.time).unwrap_or(Ordering::Equal));
+
+    let mut durations: Vec<(f64, f64)> = Vec::new(); // (bpm, 持续时间)
+    for (i, red) in red_lines.iter().enumerate() {
+        let next = red_lines.get(i + 1).map_or(end_time, |t| t.time);
+        let bpm = 60000.0 / red.val;
+        let duration = (next - red.time).max(0.0);
+        match durations.iter_mut().find(|(b, _)| (b - bpm).abs() < 1e-3) {
+            Some(entry) => entry.1 += duration,
+            None => durations.push((bpm, duration)),
+        }
+    }
+    durations
+        .into_iter()
+        .max_by(|a, b| a.1.partial_cmp(&b.1).unwrap_or(Ordering::Equal))
+        .map(|(bpm, _)| bpm)
+}
+
+/// 删除所有绿线
+pub fn strip_sv(timings: &mut Vec<OsuTimingPoint>) {
+    timings.retain(|t| t.is_timing);
+}
+
+/// 以 `base_bpm` 为基准，在每根红线处补充绿线并调整已有绿线，使流速不随 BPM 变化
+pub fn normalize_sv(timings: &mut Vec<OsuTimingPoint>, base_bpm: f64) {
+    sort_timings(timings);
+    let mut result: Vec<OsuTimingPoint> = Vec::with_capacity(timings.len());
+    let mut factor = 1.0;
+
+    for (i, tp) in timings.iter().enumerate() {
+        if tp.is_timing {
+            result.push(tp.clone());
+            factor = base_bpm / (60000.0 / tp.val);
+            // 同一时刻已有绿线时由该绿线处理
+            let has_green = timings
+                .get(i + 1)
+                .is_some_and(|next| !next.is_timing && (next.time - tp.time).abs() < SV_EPSILON);
+            if !has_green && (factor - 1.0).abs() > SV_EPSILON {
+                result.push(OsuTimingPoint {
+                    time: tp.time,
+                    val: val_from_sv(factor),
+                    is_timing: false,
+                });
+            }
+        } else {
+            result.push(OsuTimingPoint {
+                time: tp.time,
+                val: val_from_sv(sv_from_val(tp.val) * factor),
+                is_timing: false,
+            });
+        }
+    }
+    *timings = result;
+}
+
+/// 将绿线倍率限制在 [min, max]
+pub fn clamp_sv(timings: &mut [OsuTimingPoint], min: f64, max: f64) {
+    for tp in timings.iter_mut().filter(|t| !t.is_timing) {
+        tp.val = val_from_sv(sv_from_val(tp.val).clamp(min, max));
+    }
+}
+
+/// 删除冗余绿线：同一时刻的多根绿线只保留最后一根，与当前倍率相同的绿线直接删除
+pub fn merge_redundant_sv(timings: &mut Vec<OsuTimingPoint>) {
+    sort_timings(timings);
+    let mut result: Vec<OsuTimingPoint> = Vec::with_capacity(timings.len());
+    let mut current_sv = 1.0;
+
+    for (i, tp) in timings.iter().enumerate() {
+        if tp.is_timing {
+            result.push(tp.clone());
+            current_sv = 1.0; // 红线会重置 SV
+            continue;
+        }
+        let overridden = timings
+            .get(i + 1)
+            .is_some_and(|next| !next.is_timing && (next.time - tp.time).abs() < SV_EPSILON);
+        let sv = sv_from_val(tp.val);
+        if overridden || (sv - current_sv).abs() < SV_EPSILON {
+            continue;
+        }
+        current_sv = sv;
+        result.push(tp.clone());
+    }
+    *timings = result;
+}
+
+/// 依次执行变速处理，`end_time` 用于确定默认的基准 BPM
+pub fn apply_sv_operations(timings: &mut Vec<OsuTimingPoint>, ops: &[SvOperation], end_time: f64) {
+    for op in ops {
+        match *op {
+            SvOperation::Strip => strip_sv(timings),
+            SvOperation::Normalize { base_bpm } => {
+                if let Some(base) = base_bpm.or_else(|| dominant_bpm(timings, end_time)) {
+                    normalize_sv(timings, base);
+                }
+            }
+            SvOperation::Clamp { min, max } => clamp_sv(timings, min, max),
+            SvOperation::MergeRedundant => merge_redundant_sv(timings),
+        }
+    }
+}
+
+// ---------- Malody ----------
+
+fn sort_effects(effects: &mut [Effect]) {
+    effects.sort_by(|a, b| {
+        a.beat_to_float()
+            .partial_cmp(&b.beat_to_float())
+            .unwrap_or(Ordering::Equal)
+    });
+}
+
+/// 删除所有变速效果
+pub fn strip_scroll(effects: &mut Vec<Effect>) {
+    effects.clear();
+}
+
+/// 以 `base_bpm` 为基准，在每个 BPM 变化处补充变速效果并调整已有效果
+pub fn normalize_scroll(effects: &mut Vec<Effect>, times: &[Timing], base_bpm: f64) {
+    let mut times: Vec<&Timing> = times.iter().collect();
+    times.sort_by(|a, b| {
+        a.beat_to_float()
+            .partial_cmp(&b.beat_to_float())
+            .unwrap_or(Ordering::Equal)
+    });
+    sort_effects(effects);
+
+    let factor_at = |beat: f64| {
+        times
+            .iter()
+            .rev()
+            .find(|t| t.beat_to_float() <= beat)
+            .or(times.first())
+            .map_or(1.0, |t| base_bpm / t.bpm)
+    };
+    let scroll_before = |effects: &[Effect], beat: f64| {
+        effects
+            .iter()
+            .rev()
+            .find(|e| e.beat_to_float() < beat)
+            .map_or(1.0, |e| e.scroll)
+    };
+
+    let mut result: Vec<Effect> = effects
+        .iter()
+        .map(|e| Effect {
+            beat: e.beat.clone(),
+            scroll: e.scroll * factor_at(e.beat_to_float()),
+        })
+        .collect();
+    for timing in &times {
+        let beat = timing.beat_to_float();
+        let exists = effects
+            .iter()
+            .any(|e| (e.beat_to_float() - beat).abs() < SV_EPSILON);
+        let factor = base_bpm / timing.bpm;
+        if !exists && (factor - 1.0).abs() > SV_EPSILON {
+            result.push(Effect {
+                beat: timing.beat.clone(),
+                scroll: scroll_before(effects, beat) * factor,
+            });
+        }
+    }
+    sort_effects(&mut result);
+    *effects = result;
+}
+
+/// 将变速倍率限制在 [min, max]
+pub fn clamp_scroll(effects: &mut [Effect], min: f64, max: f64) {
+    for effect in effects.iter_mut() {
+        effect.scroll = effect.scroll.clamp(min, max);
+    }
+}
+
+/// 删除与当前倍率相同或被同一拍上后续效果覆盖的变速效果
+pub fn merge_redundant_scroll(effects: &mut Vec<Effect>) {
+    sort_effects(effects);
+    let mut result: Vec<Effect> = Vec::with_capacity(effects.len());
+    let mut current = 1.0;
+    for (i, effect) in effects.iter().enumerate() {
+        let beat = effect.beat_to_float();
+        let overridden = effects
+            .get(i + 1)
+            .is_some_and(|next| (next.beat_to_float() - beat).abs() < SV_EPSILON);
+        if overridden || (effect.scroll - current).abs() < SV_EPSILON {
+            continue;
+        }
+        current = effect.scroll;
+        result.push(effect.clone());
+    }
+    *effects = result;
+}
+
+/// 将 Malody 的 BPM 变化转换为红线，时间从第 0 拍开始计算。
+/// 同时返回 `end_beat` 对应的时间
+fn red_lines_from_times(times: &[Timing], end_beat: f64) -> (Vec<OsuTimingPoint>, f64) {
+    let mut times: Vec<&Timing> = times.iter().filter(|t| t.bpm > 0.0).collect();
+    times.sort_by(|a, b| {
+        a.beat_to_float()
+            .partial_cmp(&b.beat_to_float())
+            .unwrap_or(Ordering::Equal)
+    });
+
+    let mut red_lines = Vec::with_capacity(times.len());
+    let (mut time, mut prev_beat) = (0.0, 0.0);
+    let mut interval = times.first().map_or(500.0, |t| 60000.0 / t.bpm);
+    for timing in times {
+        let beat = timing.beat_to_float();
+        time += (beat - prev_beat) * interval;
+        prev_beat = beat;
+        interval = 60000.0 / timing.bpm;
+        red_lines.push(OsuTimingPoint {
+            time,
+            val: interval,
+            is_timing: true,
+        });
+    }
+    let end_time = time + (end_beat - prev_beat).max(0.0) * interval;
+    (red_lines, end_time)
+}
+
+/// 依次对 Malody 变速效果执行处理，`end_beat` 为谱面结束的拍数，用于确定默认的基准 BPM
+pub fn apply_scroll_operations(
+    effects: &mut Vec<Effect>,
+    times: &[Timing],
+    ops: &[SvOperation],
+    end_beat: f64,
+) {
+    for op in ops {
+        match *op {
+            SvOperation::Strip => strip_scroll(effects),
+            SvOperation::Normalize { base_bpm } => {
+                let dominant = || {
+                    let (red_lines, end_time) = red_lines_from_times(times, end_beat);
+                    dominant_bpm(&red_lines, end_time)
+                };
+                if let Some(base) = base_bpm.or_else(dominant) {
+                    normalize_scroll(effects, times, base);
+                }
+            }
+            SvOperation::Clamp { min, max } => clamp_scroll(effects, min, max),
+            SvOperation::MergeRedundant => merge_redundant_scroll(effects),
+        }
+    }
+}
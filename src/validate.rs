@@ -0,0 +1,415 @@
+use std::cmp::Ordering;
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+use crate::audio;
+use crate::malody_func::McData;
+use crate::osu_func::osz_func::extract_archive;
+use crate::osu_func::{HitObject, OsuData, OsuDataV128};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum Severity {
+    Info,
+    Warning,
+    Error,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Rule {
+    /// 同一列中音符重叠
+    OverlappingNotes,
+    /// 音符位于同列面条的中间
+    NoteInsideLn,
+    /// 面条过短
+    ShortLn,
+    /// 音符早于第一根红线
+    NoteBeforeFirstTiming,
+    /// 列号超出键数
+    ColumnOutOfRange,
+    MissingAudio,
+    MissingBackground,
+    /// 预览时间不在歌曲范围内
+    PreviewOutOfSong,
+    /// 同一谱面集中难度名重复
+    DuplicateVersion,
+}
+
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub rule: Rule,
+    pub severity: Severity,
+    /// 问题所在的时刻（毫秒），与具体时刻无关时为 None
+    pub time: Option<f64>,
+    pub message: String,
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time_str = self.time.map_or("-".into(), |t| format!("{:.0}ms", t));
+        write!(
+            f,
+            "[{:?}] {:?} at {}: {}",
+            self.severity, self.rule, time_str, self.message
+        )
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ValidateOptions {
+    /// 短于该长度（毫秒）的面条会被报告
+    pub min_ln_length: f64,
+    /// 同一列中间隔小于该值（毫秒）的音符视为重叠
+    pub overlap_threshold: f64,
+}
+
+impl Default for ValidateOptions {
+    fn default() -> Self {
+        Self {
+            min_ln_length: 30.0,
+            overlap_threshold: 1.0,
+        }
+    }
+}
+
+/// 校验规则使用的统一谱面表示，时间单位为毫秒
+struct ChartView {
+    column_count: u32,
+    /// (列, 开始, 结束)
+    notes: Vec<(u32, f64, Option<f64>)>,
+    first_timing: Option<f64>,
+    /// 相对音频开头的预览时间，None 表示未设置
+    preview: Option<f64>,
+}
+
+fn diagnostic(rule: Rule, severity: Severity, time: Option<f64>, message: String) -> Diagnostic {
+    Diagnostic {
+        rule,
+        severity,
+        time,
+        message,
+    }
+}
+
+fn check_chart(chart: &ChartView, options: &ValidateOptions) -> Vec<Diagnostic> {
+    let mut result = Vec::new();
+
+    for &(column, start, end) in &chart.notes {
+        if column >= chart.column_count {
+            result.push(diagnostic(
+                Rule::ColumnOutOfRange,
+                Severity::Error,
+                Some(start),
+                format!("Column {} out of range for {}K", column, chart.column_count),
+            ));
+        }
+        if let Some(first) = chart.first_timing {
+            if start < first {
+                result.push(diagnostic(
+                    Rule::NoteBeforeFirstTiming,
+                    Severity::Warning,
+                    Some(start),
+                    format!("Note in column {} is before the first timing point", column),
+                ));
+            }
+        }
+        if let Some(end) = end {
+            if end - start < options.min_ln_length {
+                result.push(diagnostic(
+                    Rule::ShortLn,
+                    Severity::Warning,
+                    Some(start),
+                    format!("LN in column {} is only {:.0}ms long", column, end - start),
+                ));
+            }
+        }
+    }
+
+    if chart.first_timing.is_none() {
+        result.push(diagnostic(
+            Rule::NoteBeforeFirstTiming,
+            Severity::Error,
+            None,
+            "No timing points in chart".into(),
+        ));
+    }
+
+    // 按列检查重叠与面条中的音符
+    let mut columns: HashMap<u32, Vec<(f64, Option<f64>)>> = HashMap::new();
+    for &(column, start, end) in &chart.notes {
+        columns.entry(column).or_default().push((start, end));
+    }
+    let mut column_ids: Vec<u32> = columns.keys().copied().collect();
+    column_ids.sort_unstable();
+    for column in column_ids {
+        let notes = columns.get_mut(&column).unwrap();
+        notes.sort_by(|a, b| a.0.partial_cmp(&b.0).unwrap_or(Ordering::Equal));
+        // 目前为止结束最晚的面条 (开始时间, 结束时间)，其中的所有音符都会被报告
+        let mut active_ln: Option<(f64, f64)> = None;
+        for (i, &(start, end)) in notes.iter().enumerate() {
+            if i > 0 && start - notes[i - 1].0 < options.overlap_threshold {
+                result.push(diagnostic(
+                    Rule::OverlappingNotes,
+                    Severity::Error,
+                    Some(start),
+                    format!("Overlapping notes in column {}", column),
+                ));
+            } else if let Some((ln_start, _)) = active_ln.filter(|&(_, e)| start <= e) {
+                result.push(diagnostic(
+                    Rule::NoteInsideLn,
+                    Severity::Error,
+                    Some(start),
+                    format!(
+                        "Note in column {} is inside the LN starting at {:.0}ms",
+                        column, ln_start
+                    ),
+                ));
+            }
+            if let Some(end) = end {
+                if active_ln.is_none_or(|(_, e)| end > e) {
+                    active_ln = Some((start, end));
+                }
+            }
+        }
+    }
+
+    if let Some(preview) = chart.preview {
+        if preview < 0.0 {
+            result.push(diagnostic(
+                Rule::PreviewOutOfSong,
+                Severity::Warning,
+                Some(preview),
+                "Preview time is before the song starts".into(),
+            ));
+        }
+    }
+
+    result.sort_by(|a, b| {
+        a.time
+            .unwrap_or(f64::NEG_INFINITY)
+            .partial_cmp(&b.time.unwrap_or(f64::NEG_INFINITY))
+            .unwrap_or(Ordering::Equal)
+    });
+    result
+}
+
+impl<H: HitObject> OsuData<H> {
+    fn chart_view(&self) -> ChartView {
+        let column_count = self.misc.circle_size;
+        ChartView {
+            column_count,
+            // 不限制列号，以便检查超出键数的音符
+            notes: self
+                .notes
+                .iter()
+                .map(|n| {
+                    (
+                        n.get_x_pos() * column_count.max(1) / 512,
+                        n.get_time().into(),
+                        n.get_end_time().map(|t| t.into()),
+                    )
+                })
+                .collect(),
+            first_timing: self
+                .timings
+                .iter()
+                .filter(|t| t.is_timing)
+                .map(|t| t.time)
+                .min_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal)),
+            preview: (self.misc.preview_time != -1).then_some(self.misc.preview_time as f64),
+        }
+    }
+
+    /// 对谱面本身执行校验（不检查资源文件）
+    pub fn validate(&self, options: &ValidateOptions) -> Vec<Diagnostic> {
+        check_chart(&self.chart_view(), options)
+    }
+}
+
+impl McData {
+    fn chart_view(&self) -> ChartView {
+        ChartView {
+            column_count: self.meta.mode_ext.column as u32,
+            notes: self
+                .note
+                .iter()
+                .filter_map(|n| {
+                    let column = n.column? as u32;
+                    let end = n
+                        .endbeat
+                        .as_ref()
+                        .map(|_| self.beat_to_time(n.end_beat_to_float()));
+                    Some((column, self.beat_to_time(n.beat_to_float()), end))
+                })
+                .collect(),
+            first_timing: self
+                .time
+                .iter()
+                .map(|t| t.beat_to_float())
+                .min_by(|a, b| a.partial_cmp(b).unwrap_or(Ordering::Equal))
+                .map(|b| self.beat_to_time(b)),
+            preview: self.meta.preview.map(|p| p as f64 - self.audio_offset()),
+        }
+    }
+
+    /// 对谱面本身执行校验（不检查资源文件）
+    pub fn validate(&self, options: &ValidateOptions) -> Vec<Diagnostic> {
+        check_chart(&self.chart_view(), options)
+    }
+
+    fn audio_file_name(&self) -> Option<&str> {
+        self.note.last().and_then(|n| n.sound.as_deref())
+    }
+}
+
+/// 校验一组 .osu / .mc 文件，资源文件按各谱面所在目录查找。
+/// 除谱面规则外，还会检查音频与背景是否存在、预览时间是否超出音频长度，
+/// 以及同一目录下同格式谱面的难度名是否重复。
+pub fn validate_files(
+    paths: &[PathBuf],
+    options: &ValidateOptions,
+) -> io::Result<Vec<(PathBuf, Vec<Diagnostic>)>> {
+    // (目录, 扩展名, 难度名) -> 第一次出现的文件
+    let mut versions: HashMap<(PathBuf, String, String), PathBuf> = HashMap::new();
+    let mut results = Vec::new();
+
+    for path in paths {
+        let ext = path
+            .extension()
+            .map(|e| e.to_string_lossy().to_lowercase())
+            .unwrap_or_default();
+        let path_str = path.to_string_lossy();
+        let (chart, mut diagnostics, audio_name, bg_name, version) = match ext.as_str() {
+            "osu" => {
+                let data = OsuDataV128::from_file(&path_str)?;
+                let diagnostics = data.validate(options);
+                (
+                    data.chart_view(),
+                    diagnostics,
+                    Some(data.misc.audio_file_name.clone()),
+                    Some(data.misc.background.clone()),
+                    data.misc.version.clone(),
+                )
+            }
+            "mc" => {
+                let data = McData::from_file(&path_str)?;
+                let diagnostics = data.validate(options);
+                (
+                    data.chart_view(),
+                    diagnostics,
+                    data.audio_file_name().map(String::from),
+                    Some(data.meta.background.clone()),
+                    data.meta.version.clone(),
+                )
+            }
+            _ => continue,
+        };
+        let dir = path.parent().unwrap_or(Path::new("")).to_path_buf();
+
+        let audio_path = audio_name.filter(|n| !n.is_empty()).map(|n| dir.join(n));
+        match &audio_path {
+            Some(audio_path) if audio_path.is_file() => {
+                // 无法解码的格式（如 mp3）跳过长度检查
+                if let (Some(preview), Ok(length)) = (chart.preview, audio::duration_ms(audio_path))
+                {
+                    if preview > length {
+                        diagnostics.push(diagnostic(
+                            Rule::PreviewOutOfSong,
+                            Severity::Warning,
+                            Some(preview),
+                            format!("Preview time is after the song ends ({:.0}ms)", length),
+                        ));
+                    }
+                }
+            }
+            _ => diagnostics.push(diagnostic(
+                Rule::MissingAudio,
+                Severity::Error,
+                None,
+                format!("Audio file not found: {:?}", audio_path.unwrap_or_default()),
+            )),
+        }
+
+        let bg_path = bg_name.filter(|n| !n.is_empty()).map(|n| dir.join(n));
+        if !bg_path.as_ref().is_some_and(|p| p.is_file()) {
+            diagnostics.push(diagnostic(
+                Rule::MissingBackground,
+                Severity::Warning,
+                None,
+                format!(
+                    "Background file not found: {:?}",
+                    bg_path.unwrap_or_default()
+                ),
+            ));
+        }
+
+        let key = (dir, ext, version.clone());
+        if let Some(first) = versions.get(&key) {
+            diagnostics.push(diagnostic(
+                Rule::DuplicateVersion,
+                Severity::Error,
+                None,
+                format!(
+                    "Difficulty name \"{}\" is also used by {:?}",
+                    version, first
+                ),
+            ));
+        } else {
+            versions.insert(key, path.clone());
+        }
+
+        results.push((path.clone(), diagnostics));
+    }
+    Ok(results)
+}
+
+/// 校验目录下所有 .osu / .mc 文件
+pub fn validate_dir(
+    dir: &Path,
+    options: &ValidateOptions,
+) -> io::Result<Vec<(PathBuf, Vec<Diagnostic>)>> {
+    let mut paths: Vec<PathBuf> = WalkDir::new(dir)
+        .into_iter()
+        .filter_map(|e| e.ok())
+        .filter(|e| e.file_type().is_file())
+        .map(|e| e.path().to_path_buf())
+        .collect();
+    paths.sort();
+    validate_files(&paths, options)
+}
+
+/// 校验 .osz / .mcz 压缩包，返回的路径为压缩包内的文件名
+pub fn validate_archive(
+    archive_path: &Path,
+    options: &ValidateOptions,
+) -> io::Result<Vec<(PathBuf, Vec<Diagnostic>)>> {
+    let temp_dir = tempdir::TempDir::new("validate")?;
+    let temp_dir_path = temp_dir.path();
+    extract_archive(archive_path, temp_dir_path)?;
+    let results = validate_dir(temp_dir_path, options)?;
+    Ok(results
+        .into_iter()
+        .map(|(path, diagnostics)| {
+            let name = path
+                .strip_prefix(temp_dir_path)
+                .unwrap_or(&path)
+                .to_path_buf();
+            (name, diagnostics)
+        })
+        .collect())
+}
+
+/// 打印校验结果，没有问题的文件不输出
+pub fn print_diagnostics(results: &[(PathBuf, Vec<Diagnostic>)]) {
+    for (path, diagnostics) in results {
+        if diagnostics.is_empty() {
+            continue;
+        }
+        eprintln!("Validation of {}:", path.display());
+        for d in diagnostics {
+            eprintln!("  {d}");
+        }
+    }
+}